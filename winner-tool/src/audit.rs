@@ -0,0 +1,381 @@
+//! Audits the integrity of a ledger copy before results derived from it are published. The
+//! blockstore is walked slot by slot to find incomplete, orphan and dead slots and the slots
+//! that are missing between the first and last slot, and the chain of blocks that scoring
+//! follows is checked back to the first slot.
+
+use crate::error::WinnerToolError;
+use serde::Serialize;
+use solana_ledger::blockstore::{Blockstore, SlotMeta};
+use solana_sdk::clock::Slot;
+use std::collections::{BTreeMap, BTreeSet};
+
+// Number of entries of each list in the summary
+const SUMMARY_ENTRIES: usize = 20;
+
+/// State of a slot in the blockstore
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SlotState {
+    pub parent_slot: Slot,
+    /// Whether every data shred of the slot has been received
+    pub is_full: bool,
+    /// Whether replay marked the slot dead
+    pub is_dead: bool,
+    pub is_root: bool,
+    /// Number of data shreds below the highest received shred that are missing
+    pub missing_shreds: u64,
+    /// Whether the shred that ends the slot has been received
+    pub last_shred_received: bool,
+}
+
+impl SlotState {
+    fn is_orphan(&self) -> bool {
+        self.parent_slot == std::u64::MAX
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct IncompleteSlot {
+    pub slot: Slot,
+    pub missing_shreds: u64,
+    pub last_shred_received: bool,
+}
+
+/// Inclusive range of slots without metadata in the blockstore
+#[derive(Debug, PartialEq, Serialize)]
+pub struct SlotRange {
+    pub start: Slot,
+    pub end: Slot,
+}
+
+/// Integrity of the blockstore between two slots
+#[derive(Debug, PartialEq, Serialize)]
+pub struct LedgerAudit {
+    pub first_slot: Slot,
+    pub last_slot: Slot,
+    /// Number of slots with metadata in the blockstore
+    pub slot_count: usize,
+    pub root_count: usize,
+    pub incomplete_slots: Vec<IncompleteSlot>,
+    pub orphan_slots: Vec<Slot>,
+    pub dead_slots: Vec<Slot>,
+    /// Slots without metadata, either skipped by their leader or missing from the ledger copy
+    pub gaps: Vec<SlotRange>,
+    /// Slots without metadata that another slot names as its parent, which are missing from the
+    /// ledger copy
+    pub missing_parents: Vec<Slot>,
+    /// Last slot of the chain of blocks that is scored
+    pub chain_slot: Slot,
+    /// Slots of the scored chain that are missing, incomplete, dead or have no parent
+    pub broken_chain_slots: Vec<Slot>,
+}
+
+impl LedgerAudit {
+    /// Whether the chain of blocks that is scored is complete back to the first slot
+    pub fn is_fit_for_scoring(&self) -> bool {
+        self.broken_chain_slots.is_empty()
+    }
+
+    /// Fails unless the ledger is fit for scoring
+    pub fn into_result(self) -> Result<Self, WinnerToolError> {
+        if self.is_fit_for_scoring() {
+            Ok(self)
+        } else {
+            Err(WinnerToolError::Ledger(format!(
+                "The chain of slot {} is broken at slots {}; the ledger is not fit for scoring, \
+                 copy it again from a validator that holds every slot of the window",
+                self.chain_slot,
+                list_summary(&self.broken_chain_slots, |slot| slot.to_string())
+            )))
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    /// Renders the audit as text, listing the first entries of every kind of problem
+    pub fn summary(&self) -> String {
+        let mut lines = vec![format!(
+            "Slots {} to {}: {} slots, {} roots",
+            self.first_slot, self.last_slot, self.slot_count, self.root_count
+        )];
+        let mut section = |title: &str, entries: Vec<String>| {
+            lines.push(format!("{}: {}", title, entries.len()));
+            for entry in entries.iter().take(SUMMARY_ENTRIES) {
+                lines.push(format!("  - {}", entry));
+            }
+            if entries.len() > SUMMARY_ENTRIES {
+                lines.push(format!("  - and {} more", entries.len() - SUMMARY_ENTRIES));
+            }
+        };
+        section(
+            "Incomplete slots",
+            self.incomplete_slots
+                .iter()
+                .map(|incomplete| {
+                    format!(
+                        "{}: {} missing shreds{}",
+                        incomplete.slot,
+                        incomplete.missing_shreds,
+                        if incomplete.last_shred_received {
+                            ""
+                        } else {
+                            ", last shred not received"
+                        }
+                    )
+                })
+                .collect(),
+        );
+        section("Orphan slots", slot_strings(&self.orphan_slots));
+        section("Dead slots", slot_strings(&self.dead_slots));
+        section(
+            "Gaps",
+            self.gaps
+                .iter()
+                .map(|gap| match gap.end - gap.start {
+                    0 => gap.start.to_string(),
+                    _ => format!("{} to {}", gap.start, gap.end),
+                })
+                .collect(),
+        );
+        section("Missing parents", slot_strings(&self.missing_parents));
+        section("Broken chain slots", slot_strings(&self.broken_chain_slots));
+        lines.push(if self.is_fit_for_scoring() {
+            format!(
+                "The chain of slot {} is complete back to slot {}: fit for scoring",
+                self.chain_slot, self.first_slot
+            )
+        } else {
+            format!(
+                "The chain of slot {} is broken: not fit for scoring",
+                self.chain_slot
+            )
+        });
+        lines.join("\n")
+    }
+}
+
+fn slot_strings(slots: &[Slot]) -> Vec<String> {
+    slots.iter().map(|slot| slot.to_string()).collect()
+}
+
+fn list_summary<T, F: Fn(&T) -> String>(entries: &[T], describe: F) -> String {
+    let mut list: Vec<String> = entries.iter().take(SUMMARY_ENTRIES).map(describe).collect();
+    if entries.len() > SUMMARY_ENTRIES {
+        list.push(format!("and {} more", entries.len() - SUMMARY_ENTRIES));
+    }
+    list.join(", ")
+}
+
+// Audits the slots from `first_slot` to `last_slot`, following the scored chain back from
+// `chain_slot`
+fn audit_slots(
+    first_slot: Slot,
+    last_slot: Slot,
+    chain_slot: Slot,
+    slots: &BTreeMap<Slot, SlotState>,
+) -> LedgerAudit {
+    let mut audit = LedgerAudit {
+        first_slot,
+        last_slot,
+        slot_count: slots.len(),
+        root_count: slots.values().filter(|state| state.is_root).count(),
+        incomplete_slots: vec![],
+        orphan_slots: vec![],
+        dead_slots: vec![],
+        gaps: vec![],
+        missing_parents: vec![],
+        chain_slot,
+        broken_chain_slots: vec![],
+    };
+
+    let mut missing_parents = BTreeSet::new();
+    let mut next_slot = first_slot;
+    for (slot, state) in slots {
+        if !state.is_full {
+            audit.incomplete_slots.push(IncompleteSlot {
+                slot: *slot,
+                missing_shreds: state.missing_shreds,
+                last_shred_received: state.last_shred_received,
+            });
+        }
+        if state.is_orphan() {
+            audit.orphan_slots.push(*slot);
+        } else if state.parent_slot >= first_slot && !slots.contains_key(&state.parent_slot) {
+            missing_parents.insert(state.parent_slot);
+        }
+        if state.is_dead {
+            audit.dead_slots.push(*slot);
+        }
+        if *slot > next_slot {
+            audit.gaps.push(SlotRange {
+                start: next_slot,
+                end: slot - 1,
+            });
+        }
+        next_slot = slot + 1;
+    }
+    if next_slot <= last_slot {
+        audit.gaps.push(SlotRange {
+            start: next_slot,
+            end: last_slot,
+        });
+    }
+    audit.missing_parents = missing_parents.into_iter().collect();
+
+    let mut slot = chain_slot;
+    loop {
+        let state = match slots.get(&slot) {
+            Some(state) => state,
+            None => {
+                audit.broken_chain_slots.push(slot);
+                break;
+            }
+        };
+        if !state.is_full || state.is_dead {
+            audit.broken_chain_slots.push(slot);
+        }
+        if slot <= first_slot {
+            break;
+        }
+        if state.is_orphan() {
+            audit.broken_chain_slots.push(slot);
+            break;
+        }
+        slot = state.parent_slot;
+    }
+    audit.broken_chain_slots.sort();
+    audit.broken_chain_slots.dedup();
+    audit
+}
+
+fn slot_state(blockstore: &Blockstore, meta: &SlotMeta) -> SlotState {
+    // Shreds below `consumed` have all been received, so only the shreds between it and the
+    // highest received shred can be missing
+    let missing_shreds = match blockstore.get_index(meta.slot) {
+        Ok(Some(index)) => (meta.consumed..meta.received)
+            .filter(|shred_index| !index.data().is_present(*shred_index))
+            .count() as u64,
+        _ => meta.received.saturating_sub(meta.consumed),
+    };
+    SlotState {
+        parent_slot: meta.parent_slot,
+        is_full: meta.is_full(),
+        is_dead: blockstore.is_dead(meta.slot),
+        is_root: blockstore.is_root(meta.slot),
+        missing_shreds,
+        last_shred_received: meta.last_index != std::u64::MAX,
+    }
+}
+
+/// Audits the blockstore from `first_slot` to `final_slot`, or to the last slot of the ledger.
+/// The scored chain is followed back from that last slot, so every slot that replay would score
+/// is covered, including the slots after the last root.
+pub fn audit_ledger(
+    blockstore: &Blockstore,
+    first_slot: Slot,
+    final_slot: Option<Slot>,
+) -> Result<LedgerAudit, WinnerToolError> {
+    let slot_metas = blockstore.slot_meta_iterator(first_slot).map_err(|err| {
+        WinnerToolError::Ledger(format!("Unable to read the ledger metadata: {:?}", err))
+    })?;
+    let slots: BTreeMap<Slot, SlotState> = slot_metas
+        .take_while(|(slot, _)| {
+            final_slot
+                .map(|final_slot| *slot <= final_slot)
+                .unwrap_or(true)
+        })
+        .map(|(slot, meta)| (slot, slot_state(blockstore, &meta)))
+        .collect();
+
+    let last_slot = final_slot
+        .or_else(|| slots.keys().next_back().cloned())
+        .unwrap_or(first_slot);
+    Ok(audit_slots(first_slot, last_slot, last_slot, &slots))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(parent_slot: Slot) -> SlotState {
+        SlotState {
+            parent_slot,
+            is_full: true,
+            is_root: true,
+            last_shred_received: true,
+            ..SlotState::default()
+        }
+    }
+
+    #[test]
+    fn test_audit_slots() {
+        // 0 <- 1 <- 4 <- 6 is complete, 2 and 3 were skipped, 5 is a dead fork of 4, 7 is
+        // incomplete and 9 is an orphan. The parent of 11, slot 10, is missing.
+        let mut slots = BTreeMap::new();
+        slots.insert(0, state(0));
+        slots.insert(1, state(0));
+        slots.insert(4, state(1));
+        slots.insert(
+            5,
+            SlotState {
+                is_dead: true,
+                is_root: false,
+                ..state(4)
+            },
+        );
+        slots.insert(6, state(4));
+        slots.insert(
+            7,
+            SlotState {
+                is_full: false,
+                is_root: false,
+                missing_shreds: 3,
+                last_shred_received: false,
+                ..state(6)
+            },
+        );
+        slots.insert(9, state(std::u64::MAX));
+        slots.insert(11, state(10));
+
+        let audit = audit_slots(0, 12, 6, &slots);
+        assert_eq!(audit.slot_count, 8);
+        assert_eq!(audit.root_count, 6);
+        assert_eq!(
+            audit.incomplete_slots,
+            vec![IncompleteSlot {
+                slot: 7,
+                missing_shreds: 3,
+                last_shred_received: false,
+            }]
+        );
+        assert_eq!(audit.orphan_slots, vec![9]);
+        assert_eq!(audit.dead_slots, vec![5]);
+        assert_eq!(
+            audit.gaps,
+            vec![
+                SlotRange { start: 2, end: 3 },
+                SlotRange { start: 8, end: 8 },
+                SlotRange { start: 10, end: 10 },
+                SlotRange { start: 12, end: 12 },
+            ]
+        );
+        assert_eq!(audit.missing_parents, vec![10]);
+        assert!(audit.is_fit_for_scoring());
+        assert!(audit.summary().ends_with(": fit for scoring"));
+
+        assert_eq!(audit_slots(0, 12, 7, &slots).broken_chain_slots, vec![7]);
+        assert_eq!(audit_slots(0, 12, 5, &slots).broken_chain_slots, vec![5]);
+        assert_eq!(audit_slots(0, 12, 9, &slots).broken_chain_slots, vec![9]);
+        assert_eq!(audit_slots(0, 12, 11, &slots).broken_chain_slots, vec![10]);
+        assert_eq!(audit_slots(4, 12, 6, &slots).broken_chain_slots, vec![]);
+        assert!(audit_slots(0, 12, 12, &slots).into_result().is_err());
+    }
+
+    #[test]
+    fn test_list_summary() {
+        let slots: Vec<Slot> = (0..25).collect();
+        assert_eq!(list_summary(&slots[..2], |slot| slot.to_string()), "0, 1");
+        assert!(list_summary(&slots, |slot| slot.to_string()).ends_with("19, and 5 more"));
+    }
+}
@@ -5,41 +5,55 @@
 //! The top 3 validators will receive the top prizes and validators will be awarded additional
 //! prizes if they perform well enough against the Solana team's validator as a baseline.
 
-use crate::utils;
-use crate::winner::{self, Winner, Winners};
-use solana_ledger::blockstore::Blockstore;
-use solana_ledger::leader_schedule_cache::LeaderScheduleCache;
-use solana_runtime::bank::Bank;
-use solana_sdk::account::Account;
-use solana_sdk::clock::Slot;
+use crate::category::{Evaluation, ScoringCategory, ScoringContext};
+use crate::checkpoint;
+use crate::error::WinnerToolError;
+use crate::events::{AccountsState, LeaderSlot, LeaderSlots, VoteAccountState};
+use crate::key_index::KeyIndex;
+use crate::utils::{self, BaselineBuckets, DEFAULT_BASELINE_BUCKETS};
+use crate::winner::{Category, Score, ValidatorDetails, Winner};
+use serde::{Deserialize, Serialize};
+use solana_sdk::clock::{Epoch, Slot};
 use solana_sdk::pubkey::Pubkey;
-use solana_vote_program::vote_state::{VoteState, MAX_LOCKOUT_HISTORY};
-use std::cmp::{max, min};
-use std::collections::{HashMap, HashSet};
+use solana_vote_program::vote_state::MAX_LOCKOUT_HISTORY;
+use std::cmp::max;
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 // Missed leader slots are weighted heavier than missing a vote
-const MISSED_LEADER_SLOT_WEIGHT: u64 = 10;
+const DEFAULT_MISSED_LEADER_SLOT_WEIGHT: u64 = 10;
+
+/// Tunable parameters of the availability category
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Parameters {
+    /// Number of missed votes that a missed leader slot counts as
+    pub missed_leader_slot_weight: u64,
+    pub baseline_buckets: BaselineBuckets,
+}
+
+impl Default for Parameters {
+    fn default() -> Self {
+        Self {
+            missed_leader_slot_weight: DEFAULT_MISSED_LEADER_SLOT_WEIGHT,
+            baseline_buckets: DEFAULT_BASELINE_BUCKETS,
+        }
+    }
+}
 
 fn normalize_winners(winners: &[(Pubkey, f64)]) -> Vec<Winner> {
     winners
         .iter()
-        .map(|(key, availability)| (*key, format_availability(*availability)))
+        .map(|(key, availability)| (*key, Score::Ratio(*availability)))
         .collect()
 }
 
-fn format_availability(availability: f64) -> String {
-    format!("{:.*}% availability", 3, availability * 100f64)
-}
-
-fn validator_credits(vote_accounts: HashMap<Pubkey, (u64, Account)>) -> HashMap<Pubkey, u64> {
+fn validator_credits(vote_accounts: &HashMap<Pubkey, VoteAccountState>) -> HashMap<Pubkey, u64> {
     let mut validator_credits = HashMap::new();
-    for (_voter_key, (_stake, account)) in vote_accounts {
-        if let Some(vote_state) = VoteState::from(&account) {
-            validator_credits
-                .entry(vote_state.node_pubkey)
-                .and_modify(|credits| *credits = max(*credits, vote_state.credits()))
-                .or_insert_with(|| vote_state.credits());
-        }
+    for vote_account in vote_accounts.values() {
+        validator_credits
+            .entry(vote_account.node_pubkey)
+            .and_modify(|credits| *credits = max(*credits, vote_account.credits))
+            .or_insert(vote_account.credits);
     }
     validator_credits
 }
@@ -49,6 +63,7 @@ fn validator_results(
     excluded_set: &HashSet<Pubkey>,
     total_credits: u64,
     validator_leader_stats: HashMap<Pubkey, LeaderStat>,
+    missed_leader_slot_weight: u64,
 ) -> Vec<(Pubkey, f64)> {
     let mut results: Vec<(Pubkey, f64)> = validator_credits
         .iter()
@@ -60,128 +75,355 @@ fn validator_results(
                 .unwrap_or_default();
             (
                 *key,
-                weighted_availability(*credits, missed_slots, total_credits),
+                weighted_availability(
+                    *credits,
+                    missed_slots,
+                    total_credits,
+                    missed_leader_slot_weight,
+                ),
             )
         })
         .collect();
-    results.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+    results.sort_by(|a, b| utils::best_first(a.1, b.1));
     results
 }
 
 /// A validator's availability is calculated from the combination of their voting effeciency and
 /// a weighted penalty for missing their leader slot.
-fn weighted_availability(credits: u64, missed_slots: u64, total_credits: u64) -> f64 {
-    credits as f64 / (MISSED_LEADER_SLOT_WEIGHT * missed_slots + total_credits) as f64
+fn weighted_availability(
+    credits: u64,
+    missed_slots: u64,
+    total_credits: u64,
+    missed_leader_slot_weight: u64,
+) -> f64 {
+    credits as f64 / (missed_leader_slot_weight * missed_slots + total_credits) as f64
+}
+
+fn validator_details(
+    validator_credits: &HashMap<Pubkey, u64>,
+    excluded_set: &HashSet<Pubkey>,
+    validator_leader_stats: &HashMap<Pubkey, LeaderStat>,
+) -> HashMap<Pubkey, ValidatorDetails> {
+    validator_credits
+        .iter()
+        .filter(|(key, _)| !excluded_set.contains(key))
+        .map(|(key, credits)| {
+            let (missed_slots, total_slots) = validator_leader_stats
+                .get(key)
+                .map(|stat| (stat.missed_slots, stat.total_slots))
+                .unwrap_or_default();
+            (
+                *key,
+                vec![
+                    ("credits", *credits),
+                    ("missed_leader_slots", missed_slots),
+                    ("total_leader_slots", total_slots),
+                ],
+            )
+        })
+        .collect()
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, Default, PartialEq)]
 struct LeaderStat {
     missed_slots: u64,
     total_slots: u64,
 }
 
-impl LeaderStat {
-    fn new(missed: bool) -> Self {
-        LeaderStat {
-            missed_slots: if missed { 1 } else { 0 },
-            total_slots: 1,
+// Leader slots of every validator within each epoch, stored by the index of the leader in the
+// leader slots, so that counting the slots of a long ledger does not hash a public key per slot
+struct EpochLeaderStats {
+    leaders: KeyIndex,
+    epochs: BTreeMap<Epoch, Vec<LeaderStat>>,
+}
+
+impl EpochLeaderStats {
+    fn record(&mut self, epoch: Epoch, leader: u32, missed: bool) {
+        let index = leader as usize;
+        let leader_stats = self.epochs.entry(epoch).or_default();
+        if leader_stats.len() <= index {
+            leader_stats.resize(index + 1, LeaderStat::default());
+        }
+        let leader_stat = &mut leader_stats[index];
+        leader_stat.total_slots += 1;
+        if missed {
+            leader_stat.missed_slots += 1;
         }
     }
+
+    // Leader stats of every validator that was leader within `epoch`
+    fn epoch(&self, epoch: Epoch) -> HashMap<Pubkey, LeaderStat> {
+        self.epochs
+            .get(&epoch)
+            .map(|leader_stats| self.by_leader(leader_stats))
+            .unwrap_or_default()
+    }
+
+    fn by_leader(&self, leader_stats: &[LeaderStat]) -> HashMap<Pubkey, LeaderStat> {
+        leader_stats
+            .iter()
+            .enumerate()
+            .filter(|(_, leader_stat)| leader_stat.total_slots > 0)
+            .map(|(index, leader_stat)| (*self.leaders.key(index as u32), leader_stat.clone()))
+            .collect()
+    }
 }
 
-fn validator_leader_stats(
-    bank: &Bank,
-    block_chain: Vec<Slot>,
-    leader_schedule_cache: &LeaderScheduleCache,
-) -> HashMap<Pubkey, LeaderStat> {
-    let mut validator_leader_stats: HashMap<Pubkey, LeaderStat> = HashMap::new();
-    let mut inc_leader_stat = |slot: u64, missed: bool| {
-        let leader = leader_schedule_cache
-            .slot_leader_at(slot, Some(bank))
-            .unwrap();
-
-        validator_leader_stats
-            .entry(leader)
-            .and_modify(|leader_stat| {
-                leader_stat.total_slots += 1;
-                if missed {
-                    leader_stat.missed_slots += 1;
-                }
-            })
-            .or_insert_with(|| LeaderStat::new(missed));
+// Leader slots of every validator, grouped by epoch
+fn epoch_leader_stats(leader_slots: &LeaderSlots) -> EpochLeaderStats {
+    let mut epoch_leader_stats = EpochLeaderStats {
+        leaders: leader_slots.leaders.clone(),
+        epochs: BTreeMap::new(),
     };
+    for leader_slot in &leader_slots.slots {
+        epoch_leader_stats.record(leader_slot.epoch, leader_slot.leader, !leader_slot.produced);
+    }
+    epoch_leader_stats
+}
 
-    let mut last_slot = bank.slot();
-    for parent_slot in block_chain.into_iter().rev() {
-        if parent_slot > 0 {
-            inc_leader_stat(parent_slot, false);
+fn validator_leader_stats(epoch_leader_stats: &EpochLeaderStats) -> HashMap<Pubkey, LeaderStat> {
+    let mut validator_leader_stats = vec![LeaderStat::default(); epoch_leader_stats.leaders.len()];
+    for leader_stats in epoch_leader_stats.epochs.values() {
+        for (total, leader_stat) in validator_leader_stats.iter_mut().zip(leader_stats) {
+            total.missed_slots += leader_stat.missed_slots;
+            total.total_slots += leader_stat.total_slots;
         }
-        for missed_slot in (parent_slot + 1..last_slot).rev() {
-            inc_leader_stat(missed_slot, true);
+    }
+    epoch_leader_stats.by_leader(&validator_leader_stats)
+}
+
+// Credits earned by each validator within each epoch, as recorded in the vote state
+fn validator_epoch_credits(
+    vote_accounts: &HashMap<Pubkey, VoteAccountState>,
+) -> BTreeMap<Epoch, HashMap<Pubkey, u64>> {
+    let mut epoch_credits: BTreeMap<Epoch, HashMap<Pubkey, u64>> = BTreeMap::new();
+    for vote_account in vote_accounts.values() {
+        for (epoch, credits, prev_credits) in &vote_account.epoch_credits {
+            let earned = credits.saturating_sub(*prev_credits);
+            epoch_credits
+                .entry(*epoch)
+                .or_default()
+                .entry(vote_account.node_pubkey)
+                .and_modify(|max_earned| *max_earned = max(*max_earned, earned))
+                .or_insert(earned);
         }
-        last_slot = parent_slot;
     }
-    validator_leader_stats
+    epoch_credits
 }
 
-pub fn compute_winners(
-    bank: &Bank,
-    blockstore: &Blockstore,
-    baseline_id: &Pubkey,
-    excluded_set: &HashSet<Pubkey>,
-    leader_schedule_cache: &LeaderScheduleCache,
-) -> Winners {
-    let block_chain = utils::block_chain(0, bank.slot(), blockstore);
-    let mut validator_credits = validator_credits(bank.vote_accounts());
-    let baseline_credits = validator_credits.remove(baseline_id).unwrap_or_else(|| {
-        panic!(
-            "Solana baseline validator {} not found in validator_credits",
-            baseline_id
-        )
-    });
-
-    let mut validator_leader_stats =
-        validator_leader_stats(bank, block_chain, &leader_schedule_cache);
-    let baseline_leader_stat = validator_leader_stats
-        .remove(baseline_id)
-        .unwrap_or_else(|| {
-            panic!(
-                "Solana baseline validator {} not found in validator_leader_stats",
-                baseline_id
-            )
+// Number of credits that could have been earned within each epoch of the window
+fn epoch_total_credits(leader_slots: &[LeaderSlot], first_slot: Slot) -> BTreeMap<Epoch, u64> {
+    let mut epoch_blocks: BTreeMap<Epoch, u64> = BTreeMap::new();
+    for leader_slot in leader_slots
+        .iter()
+        .filter(|leader_slot| leader_slot.produced)
+    {
+        *epoch_blocks.entry(leader_slot.epoch).or_default() += 1;
+    }
+    // Like the overall total, the first blocks of the ledger cannot be rooted by votes
+    if first_slot == 0 {
+        if let Some(first_epoch_blocks) = epoch_blocks.values_mut().next() {
+            *first_epoch_blocks = first_epoch_blocks.saturating_sub(MAX_LOCKOUT_HISTORY as u64);
+        }
+    }
+    epoch_blocks
+}
+
+// Credits earned since the start of the window
+fn credits_since(
+    credits: HashMap<Pubkey, u64>,
+    start_credits: &HashMap<Pubkey, u64>,
+) -> HashMap<Pubkey, u64> {
+    credits
+        .into_iter()
+        .map(|(key, credits)| {
+            let start = start_credits.get(&key).cloned().unwrap_or_default();
+            (key, credits.saturating_sub(start))
+        })
+        .collect()
+}
+
+fn epoch_results(
+    epoch_credits: &BTreeMap<Epoch, HashMap<Pubkey, u64>>,
+    epoch_leader_stats: &EpochLeaderStats,
+    epoch_total_credits: &BTreeMap<Epoch, u64>,
+    missed_leader_slot_weight: u64,
+) -> BTreeMap<Epoch, HashMap<Pubkey, Score>> {
+    let no_credits = HashMap::new();
+    epoch_total_credits
+        .iter()
+        .filter(|(_, total_credits)| **total_credits > 0)
+        .map(|(epoch, total_credits)| {
+            let credits = epoch_credits.get(epoch).unwrap_or(&no_credits);
+            let leader_stats = epoch_leader_stats.epoch(*epoch);
+            let validators: HashSet<&Pubkey> = credits.keys().chain(leader_stats.keys()).collect();
+            let scores = validators
+                .into_iter()
+                .map(|key| {
+                    let missed_slots = leader_stats
+                        .get(key)
+                        .map(|stat| stat.missed_slots)
+                        .unwrap_or_default();
+                    let availability = weighted_availability(
+                        credits.get(key).cloned().unwrap_or_default(),
+                        missed_slots,
+                        *total_credits,
+                        missed_leader_slot_weight,
+                    );
+                    (*key, Score::Ratio(availability))
+                })
+                .collect();
+            (*epoch, scores)
+        })
+        .collect()
+}
+
+pub const CATEGORY: Category = Category {
+    name: "availability",
+    title: "Highest Availability",
+    describe: describe_score,
+};
+
+fn describe_score(score: &Score) -> String {
+    format!("{} availability", score)
+}
+
+// Voting progress at the start of the scoring window
+#[derive(Deserialize, Serialize)]
+struct WindowStart {
+    block_height: u64,
+    credits: HashMap<Pubkey, u64>,
+    epoch_credits: BTreeMap<Epoch, HashMap<Pubkey, u64>>,
+}
+
+pub struct Availability {
+    parameters: Parameters,
+    window_start: Option<WindowStart>,
+}
+
+impl Availability {
+    pub fn new(parameters: Parameters) -> Self {
+        Self {
+            parameters,
+            window_start: None,
+        }
+    }
+}
+
+impl ScoringCategory for Availability {
+    fn category(&self) -> Category {
+        CATEGORY
+    }
+
+    fn on_window_start(&mut self, accounts: &AccountsState) {
+        self.window_start = Some(WindowStart {
+            block_height: accounts.block_height,
+            credits: validator_credits(&accounts.vote_accounts),
+            epoch_credits: validator_epoch_credits(&accounts.vote_accounts),
         });
+    }
+
+    fn save_state(&self) -> Result<Vec<u8>, WinnerToolError> {
+        checkpoint::serialize_state(&self.window_start)
+    }
+
+    fn restore_state(&mut self, state: &[u8]) -> Result<(), WinnerToolError> {
+        self.window_start = checkpoint::deserialize_state(state)?;
+        Ok(())
+    }
 
-    let total_blocks = bank.block_height();
-    let total_credits = total_blocks.saturating_sub(MAX_LOCKOUT_HISTORY as u64);
-    let results = validator_results(
-        validator_credits,
-        excluded_set,
-        total_credits,
-        validator_leader_stats,
-    );
-
-    let num_validators = results.len();
-    let num_winners = min(num_validators, 3);
-    let baseline = weighted_availability(
-        baseline_credits,
-        baseline_leader_stat.missed_slots,
-        total_credits,
-    );
-
-    Winners {
-        category: winner::Category::Availability(format!(
-            "Baseline: {}",
-            format_availability(baseline)
-        )),
-        top_winners: normalize_winners(&results[..num_winners]),
-        bucket_winners: utils::bucket_winners(&results, baseline, normalize_winners),
+    fn evaluate(&self, context: &ScoringContext) -> Result<Evaluation, WinnerToolError> {
+        let ScoringContext {
+            accounts,
+            leader_slots,
+            baseline_validator: baseline_id,
+            excluded_set,
+            first_slot,
+        } = *context;
+        let missed_leader_slot_weight = self.parameters.missed_leader_slot_weight;
+
+        let mut epoch_credits = validator_epoch_credits(&accounts.vote_accounts);
+        let mut validator_credits = validator_credits(&accounts.vote_accounts);
+        if let Some(window_start) = &self.window_start {
+            validator_credits = credits_since(validator_credits, &window_start.credits);
+            for (epoch, credits) in epoch_credits.iter_mut() {
+                if let Some(start_credits) = window_start.epoch_credits.get(epoch) {
+                    *credits = credits_since(credits.clone(), start_credits);
+                }
+            }
+        }
+        let baseline_credits = validator_credits.remove(baseline_id).ok_or_else(|| {
+            WinnerToolError::MissingBaseline {
+                baseline: *baseline_id,
+                measurement: "vote account",
+            }
+        })?;
+
+        let epoch_leader_stats = epoch_leader_stats(leader_slots);
+        let mut validator_leader_stats = validator_leader_stats(&epoch_leader_stats);
+        let baseline_leader_stat = validator_leader_stats.remove(baseline_id).ok_or_else(|| {
+            WinnerToolError::MissingBaseline {
+                baseline: *baseline_id,
+                measurement: "leader slots",
+            }
+        })?;
+
+        let details = validator_details(&validator_credits, excluded_set, &validator_leader_stats);
+
+        let total_credits = match &self.window_start {
+            Some(window_start) => accounts.block_height - window_start.block_height,
+            None => accounts
+                .block_height
+                .saturating_sub(MAX_LOCKOUT_HISTORY as u64),
+        };
+        let results = validator_results(
+            validator_credits,
+            excluded_set,
+            total_credits,
+            validator_leader_stats,
+            missed_leader_slot_weight,
+        );
+
+        let baseline = weighted_availability(
+            baseline_credits,
+            baseline_leader_stat.missed_slots,
+            total_credits,
+            missed_leader_slot_weight,
+        );
+
+        let epoch_scores = epoch_results(
+            &epoch_credits,
+            &epoch_leader_stats,
+            &epoch_total_credits(&leader_slots.slots, first_slot),
+            missed_leader_slot_weight,
+        );
+        let mut epoch_excluded_set = excluded_set.clone();
+        epoch_excluded_set.insert(*baseline_id);
+
+        Ok(Evaluation {
+            results: normalize_winners(&results),
+            baseline: Some(Score::Ratio(baseline)),
+            details,
+            epochs: utils::epoch_standings(epoch_scores, &epoch_excluded_set),
+        })
+    }
+
+    fn bucket_winners(&self, evaluation: &Evaluation) -> Vec<(String, Vec<Winner>)> {
+        let baseline = evaluation
+            .baseline
+            .map(|baseline| baseline.value())
+            .unwrap_or_default();
+        utils::bucket_winners(
+            &evaluation.results,
+            baseline,
+            &self.parameters.baseline_buckets,
+        )
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use solana_vote_program::vote_state::VoteInit;
 
     #[test]
     fn test_validator_results() {
@@ -221,23 +463,135 @@ mod tests {
             &excluded_set,
             total_credits,
             validator_leader_stats,
+            DEFAULT_MISSED_LEADER_SLOT_WEIGHT,
         );
         assert_eq!(results.len(), 2);
         assert_eq!(results[0], (top_validator, 1.0));
         assert_eq!(results[1], (bottom_validator, 0.05));
     }
 
+    #[test]
+    fn test_validator_details() {
+        let mut credits_map = HashMap::new();
+        let leader_validator = Pubkey::new_rand();
+        let voting_validator = Pubkey::new_rand();
+        let excluded_validator = Pubkey::new_rand();
+        credits_map.insert(leader_validator, 1000);
+        credits_map.insert(voting_validator, 100);
+        credits_map.insert(excluded_validator, 10);
+
+        let excluded_set = {
+            let mut set = HashSet::new();
+            set.insert(excluded_validator);
+            set
+        };
+
+        let mut validator_leader_stats = HashMap::new();
+        validator_leader_stats.insert(
+            leader_validator,
+            LeaderStat {
+                missed_slots: 4,
+                total_slots: 40,
+            },
+        );
+
+        let details = validator_details(&credits_map, &excluded_set, &validator_leader_stats);
+        assert_eq!(details.len(), 2);
+        assert_eq!(
+            details[&leader_validator],
+            vec![
+                ("credits", 1000),
+                ("missed_leader_slots", 4),
+                ("total_leader_slots", 40)
+            ]
+        );
+        assert_eq!(
+            details[&voting_validator],
+            vec![
+                ("credits", 100),
+                ("missed_leader_slots", 0),
+                ("total_leader_slots", 0)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validator_leader_stats() {
+        let validator = Pubkey::new_rand();
+        let mut leader_slots = LeaderSlots::default();
+        // Another leader takes the first index
+        leader_slots.push(0, 1, &Pubkey::new_rand(), true);
+        for (epoch, missed_slots) in [(0, 1), (1, 3)].iter() {
+            for slot in 0..4 {
+                leader_slots.push(slot, *epoch, &validator, slot >= *missed_slots);
+            }
+        }
+        let epoch_leader_stats = epoch_leader_stats(&leader_slots);
+
+        assert_eq!(
+            validator_leader_stats(&epoch_leader_stats)[&validator],
+            LeaderStat {
+                missed_slots: 4,
+                total_slots: 8,
+            }
+        );
+    }
+
+    #[test]
+    fn test_epoch_results() {
+        let voting_validator = Pubkey::new_rand();
+        let leader_validator = Pubkey::new_rand();
+
+        let mut epoch_credits = BTreeMap::new();
+        let mut credits = HashMap::new();
+        credits.insert(voting_validator, 50);
+        epoch_credits.insert(1, credits);
+
+        let mut leader_slots = LeaderSlots::default();
+        for slot in 0..10 {
+            leader_slots.push(slot, 1, &leader_validator, slot >= 5);
+        }
+        let epoch_leader_stats = epoch_leader_stats(&leader_slots);
+
+        let mut epoch_total_credits = BTreeMap::new();
+        epoch_total_credits.insert(0, 0);
+        epoch_total_credits.insert(1, 100);
+
+        let results = epoch_results(
+            &epoch_credits,
+            &epoch_leader_stats,
+            &epoch_total_credits,
+            DEFAULT_MISSED_LEADER_SLOT_WEIGHT,
+        );
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[&1][&voting_validator], Score::Ratio(0.5));
+        assert_eq!(results[&1][&leader_validator], Score::Ratio(0.));
+    }
+
+    #[test]
+    fn test_credits_since() {
+        let validator = Pubkey::new_rand();
+        let new_validator = Pubkey::new_rand();
+        let mut start_credits = HashMap::new();
+        start_credits.insert(validator, 40);
+
+        let mut credits = HashMap::new();
+        credits.insert(validator, 100);
+        credits.insert(new_validator, 7);
+
+        let credits = credits_since(credits, &start_credits);
+        assert_eq!(credits[&validator], 60);
+        assert_eq!(credits[&new_validator], 7);
+    }
+
     #[test]
     fn test_validator_credits() {
-        let new_vote_account = |credits: u64, validator_id: &Pubkey| -> Account {
-            let mut state = VoteState::new(&VoteInit {
-                node_pubkey: validator_id.clone(),
-                ..VoteInit::default()
-            });
-            (0..credits).for_each(|_| {
-                state.increment_credits(0);
-            });
-            Account::new_data(1, &state, &Pubkey::new_rand()).unwrap()
+        let new_vote_account = |credits: u64, validator_id: &Pubkey| -> VoteAccountState {
+            VoteAccountState {
+                node_pubkey: *validator_id,
+                credits,
+                ..VoteAccountState::default()
+            }
         };
 
         let validator1 = Pubkey::new_rand();
@@ -245,9 +599,9 @@ mod tests {
 
         let mut vote_accounts = HashMap::new();
         let voter1 = Pubkey::new_rand();
-        vote_accounts.insert(voter1.clone(), (0, new_vote_account(25, &validator1)));
-        vote_accounts.insert(Pubkey::new_rand(), (0, new_vote_account(10, &validator2)));
-        vote_accounts.insert(Pubkey::new_rand(), (0, new_vote_account(15, &validator2)));
+        vote_accounts.insert(voter1, new_vote_account(25, &validator1));
+        vote_accounts.insert(Pubkey::new_rand(), new_vote_account(10, &validator2));
+        vote_accounts.insert(Pubkey::new_rand(), new_vote_account(15, &validator2));
 
         let expected_credits = {
             let mut map = HashMap::new();
@@ -256,6 +610,52 @@ mod tests {
             map
         };
 
-        assert_eq!(expected_credits, validator_credits(vote_accounts));
+        assert_eq!(expected_credits, validator_credits(&vote_accounts));
+    }
+
+    #[test]
+    fn test_epoch_leader_stats() {
+        let leader = Pubkey::new_rand();
+        let mut leader_slots = LeaderSlots::default();
+        for (slot, epoch, produced) in &[(1, 0, true), (2, 0, false), (3, 1, false), (4, 1, true)] {
+            leader_slots.push(*slot, *epoch, &leader, *produced);
+        }
+
+        let epoch_leader_stats = epoch_leader_stats(&leader_slots);
+        assert_eq!(epoch_leader_stats.epochs.len(), 2);
+        for epoch in 0..2 {
+            assert_eq!(
+                epoch_leader_stats.epoch(epoch)[&leader],
+                LeaderStat {
+                    missed_slots: 1,
+                    total_slots: 2,
+                }
+            );
+        }
+        assert_eq!(epoch_total_credits(&leader_slots.slots, 1)[&1], 1);
+    }
+
+    #[test]
+    fn test_evaluate_without_baseline() {
+        let baseline_validator = Pubkey::new_rand();
+        let accounts = AccountsState::default();
+        let excluded_set = HashSet::new();
+        let context = ScoringContext {
+            accounts: &accounts,
+            leader_slots: &LeaderSlots::default(),
+            baseline_validator: &baseline_validator,
+            excluded_set: &excluded_set,
+            first_slot: 0,
+        };
+        match Availability::new(Parameters::default()).evaluate(&context) {
+            Err(WinnerToolError::MissingBaseline {
+                baseline,
+                measurement,
+            }) => {
+                assert_eq!(baseline, baseline_validator);
+                assert_eq!(measurement, "vote account");
+            }
+            _ => panic!("expected a missing baseline error"),
+        }
     }
 }
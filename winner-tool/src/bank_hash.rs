@@ -0,0 +1,131 @@
+//! Checks the banks of replay against trusted bank hashes, so that published results can be shown
+//! to come from the canonical chain rather than from a ledger that forked off it.
+//!
+//! A bank is only hashed once its slot has been replayed, which entry callbacks never see. The
+//! hash of a slot is therefore taken from its first child, which carries the hash of its parent,
+//! or from the frozen bank itself once replay halts at it.
+
+use crate::error::WinnerToolError;
+use solana_sdk::{clock::Slot, hash::Hash};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+/// Parses an expected bank hash given as `SLOT=HASH`
+pub fn parse_expected_bank_hash(value: &str) -> Result<(Slot, Hash), WinnerToolError> {
+    let invalid = || {
+        WinnerToolError::InvalidArgument(format!(
+            "Expected a bank hash as SLOT=HASH, got {}",
+            value
+        ))
+    };
+    let mut parts = value.splitn(2, '=');
+    let slot = parts
+        .next()
+        .and_then(|slot| slot.trim().parse::<Slot>().ok())
+        .ok_or_else(invalid)?;
+    let hash = parts
+        .next()
+        .and_then(|hash| Hash::from_str(hash.trim()).ok())
+        .ok_or_else(invalid)?;
+    Ok((slot, hash))
+}
+
+/// Hashes of the replayed banks at the slots of the expected bank hashes
+#[derive(Debug, Default)]
+pub struct BankHashCheck {
+    expected: BTreeMap<Slot, Hash>,
+    replayed: BTreeMap<Slot, Hash>,
+}
+
+impl BankHashCheck {
+    pub fn new(expected: BTreeMap<Slot, Hash>) -> Self {
+        Self {
+            expected,
+            replayed: BTreeMap::new(),
+        }
+    }
+
+    /// Records the hash of the frozen bank at `slot` if it has an expected hash
+    pub fn observe(&mut self, slot: Slot, hash: Hash) {
+        if self.expected.contains_key(&slot) {
+            self.replayed.entry(slot).or_insert(hash);
+        }
+    }
+
+    /// Slots with an expected hash
+    pub fn slots(&self) -> impl Iterator<Item = &Slot> {
+        self.expected.keys()
+    }
+
+    /// Fails if a bank replayed so far does not match its expected hash
+    pub fn check(&self) -> Result<(), WinnerToolError> {
+        for (slot, replayed) in &self.replayed {
+            let expected = self.expected[slot];
+            if *replayed != expected {
+                return Err(WinnerToolError::BankHashMismatch {
+                    slot: *slot,
+                    expected,
+                    replayed: *replayed,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Fails if a bank does not match its expected hash, or was not replayed at all
+    pub fn check_all(&self) -> Result<(), WinnerToolError> {
+        self.check()?;
+        let unchecked: Vec<String> = self
+            .expected
+            .keys()
+            .filter(|slot| !self.replayed.contains_key(slot))
+            .map(|slot| slot.to_string())
+            .collect();
+        if !unchecked.is_empty() {
+            return Err(WinnerToolError::Ledger(format!(
+                "Replay did not reach the bank of slot {} to check its expected hash; pass a slot \
+                 between the first replayed slot and the final slot to --expected-bank-hash",
+                unchecked.join(", ")
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_expected_bank_hash() {
+        let hash = Hash::new(&[1; 32]);
+        assert_eq!(
+            parse_expected_bank_hash(&format!("42={}", hash)).unwrap(),
+            (42, hash)
+        );
+        assert!(parse_expected_bank_hash(&hash.to_string()).is_err());
+        assert!(parse_expected_bank_hash(&format!("x={}", hash)).is_err());
+        assert!(parse_expected_bank_hash("42=not-a-hash").is_err());
+    }
+
+    #[test]
+    fn test_bank_hash_check() {
+        let hash = Hash::new(&[1; 32]);
+        let other_hash = Hash::new(&[2; 32]);
+        let expected = vec![(3, hash), (5, hash)].into_iter().collect();
+        let mut check = BankHashCheck::new(expected);
+
+        check.observe(2, other_hash);
+        check.observe(3, hash);
+        // Only the first hash of a slot counts
+        check.observe(3, other_hash);
+        assert!(check.check().is_ok());
+        assert!(check.check_all().is_err());
+
+        check.observe(5, other_hash);
+        match check.check_all() {
+            Err(WinnerToolError::BankHashMismatch { slot, .. }) => assert_eq!(slot, 5),
+            _ => panic!("expected a bank hash mismatch"),
+        }
+    }
+}
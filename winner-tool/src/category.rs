@@ -0,0 +1,278 @@
+//! Extension point for scoring categories. A category observes the events of the ledger while it
+//! is replayed or read back from an event log, evaluates every validator once the ledger has been
+//! observed and groups the results into prize buckets.
+
+use crate::error::WinnerToolError;
+use crate::events::{AccountsState, EntryVotes, Event, LeaderSlots};
+use crate::session::ScoringOptions;
+use crate::winner::{Category, EpochStandings, Score, ValidatorDetails, Winner, Winners};
+use crate::{availability, confirmation_latency, rewards_earned};
+use solana_sdk::{clock::Slot, pubkey::Pubkey};
+use std::cmp::min;
+use std::collections::{HashMap, HashSet};
+
+/// Ledger state available to categories once the ledger has been observed
+pub struct ScoringContext<'a> {
+    /// State of the accounts of the final bank
+    pub accounts: &'a AccountsState,
+    /// Leader of every slot in the window
+    pub leader_slots: &'a LeaderSlots,
+    pub baseline_validator: &'a Pubkey,
+    pub excluded_set: &'a HashSet<Pubkey>,
+    /// First slot of the scored window, 0 when scoring from genesis
+    pub first_slot: Slot,
+}
+
+/// Scores of every validator in a category
+pub struct Evaluation {
+    /// Every ranked validator, sorted from best to worst
+    pub results: Vec<Winner>,
+    /// Score of the Solana baseline validator, if the category is measured against one
+    pub baseline: Option<Score>,
+    pub details: HashMap<Pubkey, ValidatorDetails>,
+    /// Standings within each epoch of the ledger
+    pub epochs: Vec<EpochStandings>,
+}
+
+pub trait ScoringCategory: Send + Sync {
+    fn category(&self) -> Category;
+
+    /// Records the state of the accounts at the start of the scoring window, when the window does
+    /// not start at genesis. Called once before the first `on_entry`.
+    fn on_window_start(&mut self, _accounts: &AccountsState) {}
+
+    /// Observes the votes that landed while an entry was processed
+    fn on_entry(&mut self, _votes: &EntryVotes) {}
+
+    /// Observes the accounts when the ledger first reaches an epoch. Categories that break their
+    /// scores down by epoch record their running totals here. Called after the `on_entry` of the
+    /// first entry of the epoch, and possibly again for the same epoch when replay resumes from a
+    /// checkpoint.
+    fn on_epoch_start(&mut self, _accounts: &AccountsState) {}
+
+    fn observe(&mut self, event: &Event) {
+        match event {
+            Event::WindowStart(accounts) => self.on_window_start(accounts),
+            Event::Entry(votes) => self.on_entry(votes),
+            Event::EpochStart(accounts) => self.on_epoch_start(accounts),
+        }
+    }
+
+    /// Serializes the state recorded during replay, to be saved with a checkpoint
+    fn save_state(&self) -> Result<Vec<u8>, WinnerToolError> {
+        Ok(vec![])
+    }
+
+    /// Restores the state saved with a checkpoint before replay resumes
+    fn restore_state(&mut self, _state: &[u8]) -> Result<(), WinnerToolError> {
+        Ok(())
+    }
+
+    /// Scores every validator once the ledger has been observed
+    fn evaluate(&self, context: &ScoringContext) -> Result<Evaluation, WinnerToolError>;
+
+    /// Groups the ranked validators into prize buckets
+    fn bucket_winners(&self, evaluation: &Evaluation) -> Vec<(String, Vec<Winner>)>;
+
+    /// Evaluates the category and ranks the winners
+    fn compute_winners(&self, context: &ScoringContext) -> Result<Winners, WinnerToolError> {
+        let evaluation = self.evaluate(context)?;
+        let bucket_winners = self.bucket_winners(&evaluation);
+        let Evaluation {
+            results,
+            baseline,
+            details,
+            epochs,
+        } = evaluation;
+        let num_winners = min(results.len(), 3);
+
+        Ok(Winners {
+            category: self.category(),
+            baseline,
+            top_winners: results[..num_winners].to_vec(),
+            bucket_winners,
+            results,
+            details,
+            epochs,
+        })
+    }
+}
+
+/// Creates a category from the options of a scoring run
+pub type CategoryConstructor = fn(&ScoringOptions) -> Box<dyn ScoringCategory>;
+
+/// Categories that can be enabled by name for a scoring run
+pub struct CategoryRegistry {
+    constructors: Vec<(&'static str, CategoryConstructor)>,
+}
+
+impl Default for CategoryRegistry {
+    /// Registry of the built-in categories, in report order
+    fn default() -> Self {
+        let mut registry = Self::new();
+        registry.register(rewards_earned::CATEGORY.name, |options| {
+            Box::new(rewards_earned::RewardsEarned::new(
+                options.starting_balance,
+                options.parameters.rewards_earned.clone(),
+            ))
+        });
+        registry.register(availability::CATEGORY.name, |options| {
+            Box::new(availability::Availability::new(
+                options.parameters.availability.clone(),
+            ))
+        });
+        registry.register(confirmation_latency::CATEGORY.name, |options| {
+            Box::new(confirmation_latency::ConfirmationLatency::new(
+                options.parameters.latency.clone(),
+            ))
+        });
+        registry
+    }
+}
+
+impl CategoryRegistry {
+    /// Registry without any categories
+    pub fn new() -> Self {
+        Self {
+            constructors: vec![],
+        }
+    }
+
+    /// Registers a category, replacing any category that was registered with the same name
+    pub fn register(&mut self, name: &'static str, constructor: CategoryConstructor) {
+        match self
+            .constructors
+            .iter_mut()
+            .find(|(registered, _)| *registered == name)
+        {
+            Some(entry) => entry.1 = constructor,
+            None => self.constructors.push((name, constructor)),
+        }
+    }
+
+    /// Names of the registered categories in registration order
+    pub fn names(&self) -> Vec<&'static str> {
+        self.constructors.iter().map(|(name, _)| *name).collect()
+    }
+
+    /// Creates the named categories in the order given
+    pub fn create(
+        &self,
+        names: &[String],
+        options: &ScoringOptions,
+    ) -> Result<Vec<Box<dyn ScoringCategory>>, WinnerToolError> {
+        let mut categories = vec![];
+        for (index, name) in names.iter().enumerate() {
+            if names[..index].contains(name) {
+                return Err(WinnerToolError::InvalidArgument(format!(
+                    "Category {} is enabled more than once",
+                    name
+                )));
+            }
+            let constructor = self
+                .constructors
+                .iter()
+                .find(|(registered, _)| registered == name)
+                .map(|(_, constructor)| constructor)
+                .ok_or_else(|| {
+                    WinnerToolError::InvalidArgument(format!(
+                        "Unknown category {}, expected one of: {}",
+                        name,
+                        self.names().join(", ")
+                    ))
+                })?;
+            categories.push(constructor(options));
+        }
+        Ok(categories)
+    }
+
+    /// Creates every registered category
+    pub fn create_all(&self, options: &ScoringOptions) -> Vec<Box<dyn ScoringCategory>> {
+        self.constructors
+            .iter()
+            .map(|(_, constructor)| constructor(options))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::ScoringParameters;
+    use std::collections::BTreeMap;
+
+    fn options() -> ScoringOptions {
+        ScoringOptions {
+            baseline_validator: Some(Pubkey::new_rand()),
+            excluded_set: HashSet::new(),
+            starting_balance: 0,
+            start_slot: None,
+            final_slot: None,
+            parameters: ScoringParameters::default(),
+            checkpoint_path: None,
+            checkpoint_interval: 0,
+            resume_from: None,
+            snapshot_archive: None,
+            replay_threads: None,
+            verify_poh: false,
+            expected_bank_hashes: BTreeMap::new(),
+            metrics: None,
+        }
+    }
+
+    #[test]
+    fn test_default_registry() {
+        let registry = CategoryRegistry::default();
+        assert_eq!(
+            registry.names(),
+            vec!["rewards_earned", "availability", "latency"]
+        );
+        let names: Vec<_> = registry
+            .create_all(&options())
+            .iter()
+            .map(|category| category.category().name)
+            .collect();
+        assert_eq!(names, registry.names());
+    }
+
+    #[test]
+    fn test_create() {
+        let registry = CategoryRegistry::default();
+        let categories = registry
+            .create(
+                &["latency".to_string(), "availability".to_string()],
+                &options(),
+            )
+            .unwrap();
+        let names: Vec<_> = categories
+            .iter()
+            .map(|category| category.category().name)
+            .collect();
+        assert_eq!(names, vec!["latency", "availability"]);
+
+        assert!(registry
+            .create(&["uptime".to_string()], &options())
+            .is_err());
+        assert!(registry
+            .create(&["latency".to_string(), "latency".to_string()], &options())
+            .is_err());
+    }
+
+    #[test]
+    fn test_register() {
+        let mut registry = CategoryRegistry::new();
+        assert!(registry.create_all(&options()).is_empty());
+
+        registry.register(availability::CATEGORY.name, |options| {
+            Box::new(availability::Availability::new(
+                options.parameters.availability.clone(),
+            ))
+        });
+        registry.register(availability::CATEGORY.name, |_| {
+            Box::new(availability::Availability::new(
+                availability::Parameters::default(),
+            ))
+        });
+        assert_eq!(registry.names(), vec!["availability"]);
+    }
+}
@@ -0,0 +1,221 @@
+//! Checkpoints of a scoring run. A checkpoint holds a snapshot of a rooted bank together with the
+//! state every category has recorded up to that bank, so that a crashed or interrupted run can
+//! resume replay from the checkpoint instead of from genesis.
+//!
+//! Checkpoints are only taken at rooted slots. Replay discards the other forks when it reaches a
+//! root, so resuming from a root observes the same banks as an uninterrupted run.
+
+use crate::error::WinnerToolError;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use solana_ledger::{
+    bank_forks::{BankForks, SnapshotConfig},
+    blockstore::Blockstore,
+    snapshot_utils,
+};
+use solana_runtime::bank::Bank;
+use solana_sdk::clock::Slot;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Default number of slots between checkpoints
+pub const DEFAULT_CHECKPOINT_INTERVAL: Slot = 100_000;
+
+const STATE_FILE: &str = "state.bin";
+const SNAPSHOTS_DIR: &str = "snapshots";
+const ACCOUNTS_DIR: &str = "accounts";
+
+/// Progress of a scoring run at a checkpoint
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
+pub struct CheckpointState {
+    /// Rooted slot of the bank snapshot
+    pub slot: Slot,
+    /// First slot of the scored window the run was started with
+    pub start_slot: Option<Slot>,
+    /// Slot of the snapshot archive the run was bootstrapped from
+    pub snapshot_slot: Option<Slot>,
+    /// Whether replay had reached the start of the window
+    pub window_started: bool,
+    /// Name and serialized state of every category, in report order
+    pub categories: Vec<(String, Vec<u8>)>,
+}
+
+pub fn serialize_state<T: Serialize>(state: &T) -> Result<Vec<u8>, WinnerToolError> {
+    bincode::serialize(state)
+        .map_err(|err| WinnerToolError::Checkpoint(format!("Unable to serialize state: {}", err)))
+}
+
+pub fn deserialize_state<T: DeserializeOwned>(state: &[u8]) -> Result<T, WinnerToolError> {
+    bincode::deserialize(state)
+        .map_err(|err| WinnerToolError::Checkpoint(format!("Unable to deserialize state: {}", err)))
+}
+
+fn io_error(action: &str, path: &Path, err: std::io::Error) -> WinnerToolError {
+    WinnerToolError::Checkpoint(format!("Unable to {} {}: {}", action, path.display(), err))
+}
+
+/// First rooted slot that is at least `interval` slots after `last_slot` and before `final_slot`
+pub fn next_checkpoint_slot(
+    blockstore: &Blockstore,
+    last_slot: Slot,
+    interval: Slot,
+    final_slot: Option<Slot>,
+) -> Option<Slot> {
+    let first_slot = last_slot.saturating_add(interval.max(1));
+    let slot_meta_iterator = blockstore.slot_meta_iterator(first_slot).ok()?;
+    slot_meta_iterator
+        .map(|(slot, _meta)| slot)
+        .take_while(|slot| {
+            final_slot
+                .map(|final_slot| *slot < final_slot)
+                .unwrap_or(true)
+        })
+        .find(|slot| blockstore.is_root(*slot))
+}
+
+/// Saves a checkpoint of the bank at `state.slot` into a subdirectory of `checkpoint_path`. The
+/// state file is written last, so that an interrupted save is never mistaken for a checkpoint.
+pub fn save(
+    checkpoint_path: &Path,
+    bank_forks: &mut BankForks,
+    state: &CheckpointState,
+) -> Result<PathBuf, WinnerToolError> {
+    let dir = checkpoint_path.join(state.slot.to_string());
+    let snapshot_path = dir.join(SNAPSHOTS_DIR);
+    fs::create_dir_all(&snapshot_path).map_err(|err| io_error("create", &snapshot_path, err))?;
+
+    let bank = bank_forks.get(state.slot).cloned().ok_or_else(|| {
+        WinnerToolError::Checkpoint(format!(
+            "Bank {} is not available for a checkpoint",
+            state.slot
+        ))
+    })?;
+    bank_forks.set_snapshot_config(Some(SnapshotConfig {
+        snapshot_interval_slots: std::usize::MAX,
+        snapshot_package_output_path: dir.clone(),
+        snapshot_path,
+    }));
+    let slots_to_snapshot: Vec<Slot> = bank.src.roots().into_iter().collect();
+    let snapshot_package = bank_forks
+        .generate_snapshot(
+            state.slot,
+            &slots_to_snapshot,
+            snapshot_utils::get_snapshot_tar_path(&dir),
+        )
+        .and_then(|snapshot_package| snapshot_utils::archive_snapshot_package(&snapshot_package))
+        .map_err(|err| {
+            WinnerToolError::Checkpoint(format!(
+                "Unable to snapshot bank {}: {:?}",
+                state.slot, err
+            ))
+        });
+    bank_forks.set_snapshot_config(None);
+    snapshot_package?;
+
+    let state_file = dir.join(STATE_FILE);
+    fs::write(&state_file, serialize_state(state)?)
+        .map_err(|err| io_error("write", &state_file, err))?;
+    Ok(dir)
+}
+
+/// Checkpoint directory to resume from: `path` itself if it is a checkpoint, otherwise the
+/// checkpoint with the highest slot inside `path`
+pub fn find(path: &Path) -> Result<PathBuf, WinnerToolError> {
+    if path.join(STATE_FILE).exists() {
+        return Ok(path.to_path_buf());
+    }
+    let entries = fs::read_dir(path).map_err(|err| io_error("open", path, err))?;
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let slot = entry.file_name().to_str()?.parse::<Slot>().ok()?;
+            Some((slot, entry.path()))
+        })
+        .filter(|(_, dir)| dir.join(STATE_FILE).exists())
+        .max_by_key(|(slot, _)| *slot)
+        .map(|(_, dir)| dir)
+        .ok_or_else(|| {
+            WinnerToolError::Checkpoint(format!("No checkpoint found in {}", path.display()))
+        })
+}
+
+/// Restores the bank of a snapshot archive, keeping its accounts and unpacked snapshot in
+/// `work_path`
+pub fn bank_from_archive(archive: &Path, work_path: &Path) -> Result<Bank, WinnerToolError> {
+    // Accounts of a previous restore are stale
+    let accounts_path = work_path.join(ACCOUNTS_DIR);
+    if accounts_path.exists() {
+        fs::remove_dir_all(&accounts_path).map_err(|err| io_error("clear", &accounts_path, err))?;
+    }
+    let snapshot_path = work_path.join(SNAPSHOTS_DIR);
+    for path in &[&accounts_path, &snapshot_path] {
+        fs::create_dir_all(path).map_err(|err| io_error("create", path, err))?;
+    }
+
+    snapshot_utils::bank_from_archive(&[accounts_path], &snapshot_path, archive).map_err(|err| {
+        WinnerToolError::Checkpoint(format!(
+            "Unable to load bank snapshot from {}: {:?}",
+            archive.display(),
+            err
+        ))
+    })
+}
+
+/// Loads the bank and state of the checkpoint in `dir`, keeping the accounts of the bank in
+/// `work_path` so that the checkpoint itself is left untouched
+pub fn load(dir: &Path, work_path: &Path) -> Result<(Bank, CheckpointState), WinnerToolError> {
+    let state_file = dir.join(STATE_FILE);
+    let state: CheckpointState = fs::read(&state_file)
+        .map_err(|err| io_error("open", &state_file, err))
+        .and_then(|state| deserialize_state(&state))?;
+
+    let bank = bank_from_archive(&snapshot_utils::get_snapshot_tar_path(dir), work_path)?;
+    if bank.slot() != state.slot {
+        return Err(WinnerToolError::Checkpoint(format!(
+            "Checkpoint {} holds bank {} instead of {}",
+            dir.display(),
+            bank.slot(),
+            state.slot
+        )));
+    }
+    Ok((bank, state))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_round_trip() {
+        let state = CheckpointState {
+            slot: 42,
+            start_slot: Some(10),
+            snapshot_slot: None,
+            window_started: true,
+            categories: vec![("latency".to_string(), vec![1, 2, 3])],
+        };
+        let bytes = serialize_state(&state).unwrap();
+        assert_eq!(deserialize_state::<CheckpointState>(&bytes).unwrap(), state);
+        assert!(deserialize_state::<CheckpointState>(&bytes[..4]).is_err());
+    }
+
+    #[test]
+    fn test_find() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let checkpoint_path = temp_dir.path().join("checkpoints");
+        assert!(find(&checkpoint_path).is_err());
+
+        for slot in &[100, 2000, 300] {
+            let dir = checkpoint_path.join(slot.to_string());
+            fs::create_dir_all(&dir).unwrap();
+            if *slot != 2000 {
+                fs::write(dir.join(STATE_FILE), b"").unwrap();
+            }
+        }
+        // Interrupted checkpoints without a state file are skipped
+        assert_eq!(find(&checkpoint_path).unwrap(), checkpoint_path.join("300"));
+        let checkpoint = checkpoint_path.join("100");
+        assert_eq!(find(&checkpoint).unwrap(), checkpoint);
+    }
+}
@@ -0,0 +1,209 @@
+//! Competition configuration file that captures every input of a scoring run, so that results
+//! can be reproduced by committing a single file:
+//!
+//! ```yaml
+//! ledger: /path/to/tds/ledger
+//! baseline_validator: boot1Z6jb15CLqpaMTn2CxktktwZpRAVAgHZEW6SxQ7
+//! exclude_pubkeys:
+//!   - rpc1io1gmhuEq26wTBARGJfGGw48S7GYaHfKVEf9Dvv
+//! exclude_pubkey_files:
+//!   - validators/internal-username.yml
+//! start_slot: 1000000
+//! final_slot: 1234567
+//! starting_balance: 2
+//! pubkey_map_files:
+//!   - validators/all-username.yml
+//! categories: [rewards_earned, availability, latency]
+//! parameters:
+//!   availability:
+//!     missed_leader_slot_weight: 10
+//!     baseline_buckets: [0.95, 0.75, 0.5]
+//!   latency:
+//!     max_vote_delay: 10
+//!     baseline_buckets: [0.95, 0.75, 0.5]
+//!   rewards_earned:
+//!     bucket_percentiles: [25, 50, 90]
+//! ```
+//!
+//! Relative paths are resolved against the directory containing the configuration file.
+
+use crate::error::WinnerToolError;
+use crate::session::ScoringParameters;
+use serde::Deserialize;
+use solana_sdk::{
+    clock::{Epoch, Slot},
+    pubkey::Pubkey,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Ledger directory
+    pub ledger: Option<PathBuf>,
+    /// Identity of the Solana baseline validator
+    pub baseline_validator: Option<String>,
+    /// Identities that are left out of the results
+    pub exclude_pubkeys: Vec<String>,
+    /// YAML files keyed by identity, like `validators/internal-username.yml`, whose identities
+    /// are left out of the results
+    pub exclude_pubkey_files: Vec<PathBuf>,
+    /// First slot of the scored window
+    pub start_slot: Option<Slot>,
+    /// First epoch of the scored window, when `start_slot` is not set
+    pub start_epoch: Option<Epoch>,
+    /// Final slot of the TdS ledger
+    pub final_slot: Option<Slot>,
+    /// Last epoch of the scored window, when `final_slot` is not set
+    pub end_epoch: Option<Epoch>,
+    /// Starting balance of validators in SOL
+    pub starting_balance: Option<f64>,
+    /// YAML files that map validator identity pubkeys to keybase user ids
+    pub pubkey_map_files: Vec<PathBuf>,
+    /// Names of the categories to score, every registered category if empty
+    pub categories: Vec<String>,
+    /// Tunable parameters of every category
+    pub parameters: ScoringParameters,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self, WinnerToolError> {
+        let contents = read_file(path)?;
+        let mut config: Config =
+            serde_yaml::from_str(&contents).map_err(|err| parse_error(path, err))?;
+        if let Some(base) = path.parent() {
+            config.resolve_paths(base);
+        }
+        Ok(config)
+    }
+
+    fn resolve_paths(&mut self, base: &Path) {
+        let resolve = |path: &mut PathBuf| {
+            if path.is_relative() {
+                let resolved = base.join(&*path);
+                *path = resolved;
+            }
+        };
+        if let Some(ledger) = self.ledger.as_mut() {
+            resolve(ledger);
+        }
+        self.exclude_pubkey_files.iter_mut().for_each(resolve);
+        self.pubkey_map_files.iter_mut().for_each(resolve);
+    }
+
+    pub fn baseline_validator(&self) -> Result<Option<Pubkey>, WinnerToolError> {
+        self.baseline_validator
+            .as_ref()
+            .map(|pubkey| parse_pubkey(pubkey))
+            .transpose()
+    }
+
+    /// Identities listed in `exclude_pubkeys` and in every `exclude_pubkey_files` entry
+    pub fn excluded_set(&self) -> Result<HashSet<Pubkey>, WinnerToolError> {
+        let mut excluded_set = HashSet::new();
+        for pubkey in &self.exclude_pubkeys {
+            excluded_set.insert(parse_pubkey(pubkey)?);
+        }
+        for path in &self.exclude_pubkey_files {
+            let contents = read_file(path)?;
+            for pubkey in yaml_map_keys(&contents).map_err(|err| parse_error(path, err))? {
+                excluded_set.insert(parse_pubkey(&pubkey)?);
+            }
+        }
+        Ok(excluded_set)
+    }
+
+    /// Merged contents of every `pubkey_map_files` entry
+    pub fn pubkey_map(&self) -> Result<HashMap<String, String>, WinnerToolError> {
+        let mut pubkey_map = HashMap::new();
+        for path in &self.pubkey_map_files {
+            pubkey_map.extend(load_pubkey_map(path)?);
+        }
+        Ok(pubkey_map)
+    }
+}
+
+fn read_file(path: &Path) -> Result<String, WinnerToolError> {
+    fs::read_to_string(path).map_err(|err| {
+        WinnerToolError::Config(format!("Unable to open {}: {}", path.display(), err))
+    })
+}
+
+fn parse_error(path: &Path, err: serde_yaml::Error) -> WinnerToolError {
+    WinnerToolError::Config(format!("Unable to parse {}: {}", path.display(), err))
+}
+
+fn parse_pubkey(pubkey: &str) -> Result<Pubkey, WinnerToolError> {
+    Pubkey::from_str(pubkey)
+        .map_err(|err| WinnerToolError::Config(format!("Invalid pubkey {}: {:?}", pubkey, err)))
+}
+
+// Returns the keys of a YAML mapping, treating an empty document as an empty mapping
+fn yaml_map_keys(contents: &str) -> Result<Vec<String>, serde_yaml::Error> {
+    if contents.trim().is_empty() {
+        return Ok(vec![]);
+    }
+    let map: HashMap<String, serde_yaml::Value> = serde_yaml::from_str(contents)?;
+    Ok(map.into_iter().map(|(key, _)| key).collect())
+}
+
+/// Loads a YAML file that maps validator identity pubkeys to keybase user ids
+pub fn load_pubkey_map(path: &Path) -> Result<HashMap<String, String>, WinnerToolError> {
+    let contents = read_file(path)?;
+    if contents.trim().is_empty() {
+        return Ok(HashMap::new());
+    }
+    serde_yaml::from_str(&contents).map_err(|err| parse_error(path, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_paths() {
+        let mut config: Config = serde_yaml::from_str(
+            "ledger: ledger\npubkey_map_files: [/abs/users.yml, validators/all-username.yml]",
+        )
+        .unwrap();
+        config.resolve_paths(Path::new("stages/stage1"));
+        assert_eq!(config.ledger, Some(PathBuf::from("stages/stage1/ledger")));
+        assert_eq!(
+            config.pubkey_map_files,
+            vec![
+                PathBuf::from("/abs/users.yml"),
+                PathBuf::from("stages/stage1/validators/all-username.yml")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parameters() {
+        let config: Config =
+            serde_yaml::from_str("parameters:\n  latency:\n    max_vote_delay: 4\n").unwrap();
+        assert_eq!(config.parameters.latency.max_vote_delay, 4);
+        assert_eq!(
+            config.parameters.availability,
+            ScoringParameters::default().availability
+        );
+    }
+
+    #[test]
+    fn test_unknown_field() {
+        assert!(serde_yaml::from_str::<Config>("final_slots: 10").is_err());
+    }
+
+    #[test]
+    fn test_yaml_map_keys() {
+        assert!(yaml_map_keys("").unwrap().is_empty());
+
+        let pubkey = Pubkey::new_rand();
+        let keys = yaml_map_keys(&format!("{}:\n  balance: 500000000000\n", pubkey)).unwrap();
+        assert_eq!(keys, vec![pubkey.to_string()]);
+    }
+}
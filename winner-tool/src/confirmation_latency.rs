@@ -2,71 +2,116 @@
 //! order of incoming validator votes. Validators earn one point for votes received before the
 //! average and lose one point for votes received later than the average.
 
-use crate::utils;
-use crate::winner::{self, Winner, Winners};
-use solana_runtime::bank::Bank;
-use solana_sdk::account::Account;
+use crate::category::{Evaluation, ScoringCategory, ScoringContext};
+use crate::checkpoint;
+use crate::error::WinnerToolError;
+use crate::events::{AccountsState, EntryVotes, VoteAccountState};
+use crate::key_index::{IndexSet, KeyIndex};
+use crate::utils::{self, BaselineBuckets, EpochTotals, DEFAULT_BASELINE_BUCKETS};
+use crate::winner::{Category, Score, Winner};
+use serde::{Deserialize, Serialize};
 use solana_sdk::clock::Slot;
-use solana_sdk::hash::Hash;
 use solana_sdk::pubkey::Pubkey;
-use solana_vote_program::vote_state::VoteState;
-use std::cmp::{max, min};
+use std::cmp::max;
 use std::collections::{BTreeMap, HashMap, HashSet};
 
-// Votes received `MAX_VOTE_DELAY` slots after the current slot will not be counted towards a
+// Votes received `max_vote_delay` slots after the current slot will not be counted towards a
 // validator's latency score because this delay implies an availability issue rather than a latency
 // issue.
-const MAX_VOTE_DELAY: u64 = 10;
+const DEFAULT_MAX_VOTE_DELAY: u64 = 10;
+
+/// Tunable parameters of the confirmation latency category
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Parameters {
+    /// Number of slots after which a vote is too late to count towards the latency score
+    pub max_vote_delay: u64,
+    pub baseline_buckets: BaselineBuckets,
+}
+
+impl Default for Parameters {
+    fn default() -> Self {
+        Self {
+            max_vote_delay: DEFAULT_MAX_VOTE_DELAY,
+            baseline_buckets: DEFAULT_BASELINE_BUCKETS,
+        }
+    }
+}
 
-#[derive(Clone, Default, Debug, PartialEq)]
+#[derive(Clone, Default, Debug, Deserialize, PartialEq, Serialize)]
 pub struct VoterEntry {
     latency_score: i64, // +1 for low latency, -1 for high latency
     last_slot: Slot,
-    last_hash: Hash,
 }
 
-// Checks `bank` voter state against the latest tracked `voter_record`. If voter hash has updated,
-// check if the voter has new votes to record. Returns a record of votes seen in this checkpoint
-// grouped by slot.
+/// Voting record of every vote account, stored by the index of the vote account so that the
+/// votes of a slot can be tracked as a set of indexes
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct VoterRecord {
+    voters: KeyIndex,
+    entries: Vec<VoterEntry>,
+}
+
+impl VoterRecord {
+    /// Index and entry of `voter_key`, which is added without any votes if it is new
+    pub fn entry(&mut self, voter_key: &Pubkey) -> (u32, &mut VoterEntry) {
+        let index = self.voters.insert(voter_key);
+        if index as usize == self.entries.len() {
+            self.entries.push(VoterEntry::default());
+        }
+        (index, &mut self.entries[index as usize])
+    }
+
+    pub fn get(&self, voter_key: &Pubkey) -> Option<&VoterEntry> {
+        self.voters
+            .get(voter_key)
+            .map(|index| &self.entries[index as usize])
+    }
+
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = (&'a Pubkey, &'a VoterEntry)> + 'a {
+        self.voters.keys().iter().zip(self.entries.iter())
+    }
+}
+
+// Checks the votes observed after an entry against the latest tracked `voter_record`, recording
+// the votes that are new. Returns the voters seen in this checkpoint grouped by slot.
 fn voter_checkpoint(
     slot: Slot,
-    vote_accounts: HashMap<Pubkey, (u64, Account)>,
+    votes: &[(Pubkey, Vec<Slot>)],
     voter_record: &mut VoterRecord,
-) -> HashMap<Slot, HashSet<Pubkey>> {
-    let mut slot_voters: HashMap<Slot, HashSet<Pubkey>> = HashMap::new();
-    for (voter_key, (_stake, account)) in vote_accounts {
-        let mut voter_entry = voter_record
-            .entry(voter_key)
-            .or_insert_with(VoterEntry::default);
-        if voter_entry.last_hash != account.hash {
-            voter_entry.last_hash = account.hash;
-            let vote_state = VoteState::from(&account).unwrap();
-            for lockout in vote_state.votes.iter().rev() {
-                if lockout.slot <= voter_entry.last_slot {
-                    break;
-                } else if lockout.slot < slot.saturating_sub(MAX_VOTE_DELAY) {
-                    // vote was very late, don't track latency
-                } else {
-                    let voters = slot_voters.entry(lockout.slot).or_insert_with(HashSet::new);
-                    voters.insert(voter_key);
-                }
+    max_vote_delay: u64,
+) -> HashMap<Slot, IndexSet> {
+    let mut slot_voters: HashMap<Slot, IndexSet> = HashMap::new();
+    for (voter_key, vote_slots) in votes {
+        let (voter_index, voter_entry) = voter_record.entry(voter_key);
+        for vote_slot in vote_slots.iter().rev() {
+            if *vote_slot <= voter_entry.last_slot {
+                break;
+            } else if *vote_slot < slot.saturating_sub(max_vote_delay) {
+                // vote was very late, don't track latency
+            } else {
+                slot_voters
+                    .entry(*vote_slot)
+                    .or_default()
+                    .insert(voter_index);
             }
-            voter_entry.last_slot = vote_state.votes.back().unwrap().slot;
+        }
+        if let Some(last_slot) = vote_slots.last() {
+            voter_entry.last_slot = max(voter_entry.last_slot, *last_slot);
         }
     }
     slot_voters
 }
 
 // Assign latency scores to voters depending on how early their vote was recorded.
-fn score_voters(voters: &[HashSet<Pubkey>], voter_record: &mut HashMap<Pubkey, VoterEntry>) {
+fn score_voters(voters: &[IndexSet], voter_record: &mut VoterRecord) {
     let total_voters: usize = voters.iter().map(|set| set.len()).sum();
     let mut voters_seen = 0;
     for voter_set in voters {
         let is_low_latency = voters_seen < max(1, total_voters / 2);
         let score_differential = if is_low_latency { 1 } else { -1 };
-        for voter in voter_set {
-            let voter_entry = voter_record.get_mut(&voter).unwrap();
-            voter_entry.latency_score += score_differential;
+        for voter_index in voter_set.iter() {
+            voter_record.entries[voter_index as usize].latency_score += score_differential;
         }
         voters_seen += voter_set.len();
     }
@@ -75,24 +120,22 @@ fn score_voters(voters: &[HashSet<Pubkey>], voter_record: &mut HashMap<Pubkey, V
 fn normalize_winners(winners: &[(Pubkey, f64)]) -> Vec<Winner> {
     winners
         .iter()
-        .map(|(key, latency_score)| (*key, format!("Latency score: {:.*}", 0, latency_score)))
+        .map(|(key, latency_score)| (*key, Score::Points(*latency_score as i64)))
         .collect()
 }
 
-/// Snapshot of the voting record of a validator
-pub type VoterRecord = HashMap<Pubkey, VoterEntry>;
-
-/// Ordered record of votes for each slot
-pub type SlotVoterSegments = BTreeMap<u64, Vec<HashSet<Pubkey>>>;
+/// Ordered record of votes for each slot, as sets of voter indexes of the `VoterRecord`
+pub type SlotVoterSegments = BTreeMap<u64, Vec<IndexSet>>;
 
 /// Track voter latency by checkpointing the voter record after each entry.
 pub fn on_entry(
     bank_slot: Slot,
-    vote_accounts: HashMap<Pubkey, (u64, Account)>,
+    votes: &[(Pubkey, Vec<Slot>)],
     voter_record: &mut VoterRecord,
     slot_voter_segments: &mut SlotVoterSegments,
+    max_vote_delay: u64,
 ) {
-    let mut slot_voters = voter_checkpoint(bank_slot, vote_accounts, voter_record);
+    let mut slot_voters = voter_checkpoint(bank_slot, votes, voter_record, max_vote_delay);
     for (slot, voters) in slot_voters.drain() {
         let slot_entry = slot_voter_segments.entry(slot).or_insert_with(Vec::new);
         slot_entry.push(voters);
@@ -102,7 +145,7 @@ pub fn on_entry(
     let old_slots: Vec<_> = slot_voter_segments
         .iter()
         .map(|(slot, _)| *slot)
-        .take_while(|slot| *slot < bank_slot.saturating_sub(MAX_VOTE_DELAY))
+        .take_while(|slot| *slot < bank_slot.saturating_sub(max_vote_delay))
         .collect();
     for old_slot in old_slots {
         let voter_segments = slot_voter_segments.remove(&old_slot).unwrap();
@@ -113,128 +156,265 @@ pub fn on_entry(
 fn validator_results(
     baseline_id: &Pubkey,
     excluded_set: &HashSet<Pubkey>,
-    vote_accounts: HashMap<Pubkey, (u64, Account)>,
-    voter_record: &mut VoterRecord,
-) -> (Vec<(Pubkey, f64)>, f64) {
+    vote_accounts: &HashMap<Pubkey, VoteAccountState>,
+    voter_record: &VoterRecord,
+) -> Result<(Vec<(Pubkey, f64)>, f64), WinnerToolError> {
     let mut validator_latency: HashMap<Pubkey, i64> = HashMap::new();
-    for (voter_key, (_stake, account)) in vote_accounts {
-        let vote_state = VoteState::from(&account).unwrap();
-        let voter_entry = voter_record.remove(&voter_key).unwrap();
+    for (voter_key, vote_account) in vote_accounts {
+        // Vote accounts that have not voted within the window have no score yet
+        let latency_score = voter_record
+            .get(voter_key)
+            .map(|voter_entry| voter_entry.latency_score)
+            .unwrap_or(0);
         // It's possible that there are multiple vote accounts attributed to a validator
         //   so use the max score when duplicates are found
         let entry = validator_latency
-            .entry(vote_state.node_pubkey)
+            .entry(vote_account.node_pubkey)
             .or_insert(std::i64::MIN);
-        *entry = max(*entry, voter_entry.latency_score);
+        *entry = max(*entry, latency_score);
     }
 
-    let baseline = validator_latency.remove(baseline_id).unwrap() as f64;
+    let baseline =
+        validator_latency
+            .remove(baseline_id)
+            .ok_or_else(|| WinnerToolError::MissingBaseline {
+                baseline: *baseline_id,
+                measurement: "vote account",
+            })? as f64;
     let mut results: Vec<(Pubkey, f64)> = validator_latency
         .iter()
         .filter(|(key, _)| !excluded_set.contains(key))
         .map(|(key, latency)| (*key, *latency as f64))
         .collect();
-    results.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
-    (results, baseline)
+    results.sort_by(|a, b| utils::best_first(a.1, b.1));
+    Ok((results, baseline))
 }
 
-pub fn compute_winners(
-    bank: &Bank,
-    baseline_id: &Pubkey,
-    excluded_set: &HashSet<Pubkey>,
-    voter_record: &mut VoterRecord,
-    slot_voter_segments: &mut SlotVoterSegments,
-) -> Winners {
-    // Score the remaining segments leftover from entry processing
-    for (_, voter_segments) in slot_voter_segments.iter() {
-        score_voters(voter_segments, voter_record);
+// Starts tracking every voter from its latest vote, so that votes cast before the window are not
+// scored as if they had just arrived
+fn window_voter_record(vote_accounts: &HashMap<Pubkey, VoteAccountState>) -> VoterRecord {
+    let mut voter_record = VoterRecord::default();
+    for (voter_key, vote_account) in vote_accounts {
+        let (_, voter_entry) = voter_record.entry(voter_key);
+        voter_entry.last_slot = vote_account.last_vote_slot.unwrap_or(0);
+    }
+    voter_record
+}
+
+fn voter_scores(voter_record: &VoterRecord) -> HashMap<Pubkey, i64> {
+    voter_record
+        .iter()
+        .map(|(voter_key, voter_entry)| (*voter_key, voter_entry.latency_score))
+        .collect()
+}
+
+// Attributes the points of each voter to its validator, using the max when a validator has
+// multiple vote accounts
+fn validator_points(
+    vote_accounts: &HashMap<Pubkey, VoteAccountState>,
+    voter_points: HashMap<Pubkey, i64>,
+) -> HashMap<Pubkey, Score> {
+    let mut validator_points: HashMap<Pubkey, i64> = HashMap::new();
+    for (voter_key, points) in voter_points {
+        if let Some(vote_account) = vote_accounts.get(&voter_key) {
+            let entry = validator_points
+                .entry(vote_account.node_pubkey)
+                .or_insert(points);
+            *entry = max(*entry, points);
+        }
+    }
+    validator_points
+        .into_iter()
+        .map(|(key, points)| (key, Score::Points(points)))
+        .collect()
+}
+
+pub const CATEGORY: Category = Category {
+    name: "latency",
+    title: "Confirmation Latency",
+    describe: describe_score,
+};
+
+fn describe_score(score: &Score) -> String {
+    format!("Latency score: {}", score)
+}
+
+// Votes tracked during replay
+#[derive(Default, Deserialize, Serialize)]
+struct State {
+    // Votes are only tracked consistently when replay resumes with the same delay
+    max_vote_delay: u64,
+    voter_record: VoterRecord,
+    slot_voter_segments: SlotVoterSegments,
+    epoch_totals: EpochTotals,
+}
+
+pub struct ConfirmationLatency {
+    parameters: Parameters,
+    state: State,
+}
+
+impl ConfirmationLatency {
+    pub fn new(parameters: Parameters) -> Self {
+        Self {
+            state: State {
+                max_vote_delay: parameters.max_vote_delay,
+                ..State::default()
+            },
+            parameters,
+        }
+    }
+}
+
+impl ScoringCategory for ConfirmationLatency {
+    fn category(&self) -> Category {
+        CATEGORY
     }
 
-    let vote_accounts = bank.vote_accounts();
-    let (results, baseline) =
-        validator_results(baseline_id, excluded_set, vote_accounts, voter_record);
-    let num_validators = results.len();
-    let num_winners = min(num_validators, 3);
-
-    Winners {
-        category: winner::Category::ConfirmationLatency(format!(
-            "Baseline latency score: {}",
-            baseline
-        )),
-        top_winners: normalize_winners(&results[..num_winners]),
-        bucket_winners: utils::bucket_winners(&results, baseline as f64, normalize_winners),
+    fn on_window_start(&mut self, accounts: &AccountsState) {
+        self.state.voter_record = window_voter_record(&accounts.vote_accounts);
+    }
+
+    fn on_entry(&mut self, votes: &EntryVotes) {
+        on_entry(
+            votes.slot,
+            &votes.votes,
+            &mut self.state.voter_record,
+            &mut self.state.slot_voter_segments,
+            self.parameters.max_vote_delay,
+        );
+    }
+
+    fn on_epoch_start(&mut self, accounts: &AccountsState) {
+        // Voters that have not voted yet start the epoch without points
+        for voter_key in accounts.vote_accounts.keys() {
+            self.state.voter_record.entry(voter_key);
+        }
+        if self.state.epoch_totals.is_new_epoch(accounts.epoch) {
+            let totals = voter_scores(&self.state.voter_record);
+            self.state.epoch_totals.record(accounts.epoch, totals);
+        }
+    }
+
+    fn save_state(&self) -> Result<Vec<u8>, WinnerToolError> {
+        checkpoint::serialize_state(&self.state)
+    }
+
+    fn restore_state(&mut self, state: &[u8]) -> Result<(), WinnerToolError> {
+        let state: State = checkpoint::deserialize_state(state)?;
+        if state.max_vote_delay != self.parameters.max_vote_delay {
+            return Err(WinnerToolError::Checkpoint(format!(
+                "Checkpoint was recorded with a max_vote_delay of {} instead of {}",
+                state.max_vote_delay, self.parameters.max_vote_delay
+            )));
+        }
+        self.state = state;
+        Ok(())
+    }
+
+    fn evaluate(&self, context: &ScoringContext) -> Result<Evaluation, WinnerToolError> {
+        // Score the remaining segments leftover from entry processing without disturbing the
+        // tracked record, so that replay can continue afterwards
+        let mut voter_record = self.state.voter_record.clone();
+        for voter_segments in self.state.slot_voter_segments.values() {
+            score_voters(voter_segments, &mut voter_record);
+        }
+
+        let vote_accounts = &context.accounts.vote_accounts;
+        let voter_deltas = self
+            .state
+            .epoch_totals
+            .deltas(&voter_scores(&voter_record), 0);
+        let epoch_scores = voter_deltas
+            .into_iter()
+            .map(|(epoch, deltas)| (epoch, validator_points(vote_accounts, deltas)))
+            .collect();
+        let mut epoch_excluded_set = context.excluded_set.clone();
+        epoch_excluded_set.insert(*context.baseline_validator);
+
+        let (results, baseline) = validator_results(
+            context.baseline_validator,
+            context.excluded_set,
+            vote_accounts,
+            &voter_record,
+        )?;
+
+        Ok(Evaluation {
+            results: normalize_winners(&results),
+            baseline: Some(Score::Points(baseline as i64)),
+            details: HashMap::new(),
+            epochs: utils::epoch_standings(epoch_scores, &epoch_excluded_set),
+        })
+    }
+
+    fn bucket_winners(&self, evaluation: &Evaluation) -> Vec<(String, Vec<Winner>)> {
+        let baseline = evaluation
+            .baseline
+            .map(|baseline| baseline.value())
+            .unwrap_or_default();
+        utils::bucket_winners(
+            &evaluation.results,
+            baseline,
+            &self.parameters.baseline_buckets,
+        )
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use solana_sdk::hash::hash;
-    use solana_vote_program::vote_state::{Lockout, VoteInit};
     use std::ops::Range;
 
+    // Voter indexes of `voters`, which must be in `voter_record`
+    fn voter_set(voter_record: &VoterRecord, voters: &[Pubkey]) -> IndexSet {
+        let mut voter_set = IndexSet::default();
+        for voter in voters {
+            voter_set.insert(voter_record.voters.get(voter).unwrap());
+        }
+        voter_set
+    }
+
     #[test]
     fn test_voter_checkpoint() {
         let current_slot = 100;
-        let new_vote_account = |vote_range: Range<Slot>| -> Account {
-            let mut state = VoteState::default();
-            for vote_slot in vote_range {
-                state.votes.push_back(Lockout::new(vote_slot));
-            }
-            let owner = Pubkey::new_rand();
-            let mut account = Account::new_data(1, &state, &owner).unwrap();
-            account.hash = hash(owner.as_ref());
-            account
-        };
+        let new_votes = |vote_range: Range<Slot>| -> Vec<Slot> { vote_range.collect() };
 
         let voter1 = Pubkey::new_rand();
         let voter2 = Pubkey::new_rand();
         let voter3 = Pubkey::new_rand();
 
-        let mut vote_accounts = HashMap::new();
-        let mut voter_record = HashMap::new();
+        let mut votes = vec![];
+        let mut voter_record = VoterRecord::default();
 
         // Discard too old votes and add to voter_record
-        let too_old_slot = current_slot - MAX_VOTE_DELAY - 1;
-        let voter1_account = new_vote_account(too_old_slot..current_slot + 1);
-        let voter1_hash = voter1_account.hash;
-        vote_accounts.insert(voter1.clone(), (0, voter1_account));
+        let too_old_slot = current_slot - DEFAULT_MAX_VOTE_DELAY - 1;
+        votes.push((voter1, new_votes(too_old_slot..current_slot + 1)));
 
         // Up until last_slot
-        let voter2_account = new_vote_account(0..current_slot + 1);
-        let voter2_hash = voter2_account.hash;
-        vote_accounts.insert(voter2.clone(), (0, voter2_account));
-        voter_record.insert(
-            voter2,
-            VoterEntry {
-                last_slot: current_slot - 1,
-                ..VoterEntry::default()
-            },
-        );
+        votes.push((voter2, new_votes(0..current_slot + 1)));
+        voter_record.entry(&voter2).1.last_slot = current_slot - 1;
 
-        // Ignore same hash
-        let voter3_account = new_vote_account(current_slot..current_slot + 1);
-        let voter3_hash = voter3_account.hash;
+        // Ignore voters without new votes
         let voter3_entry = VoterEntry {
-            last_hash: voter3_hash,
+            last_slot: current_slot - 1,
             ..VoterEntry::default()
         };
-        vote_accounts.insert(voter3.clone(), (0, voter3_account));
-        voter_record.insert(voter3, voter3_entry.clone());
+        *voter_record.entry(&voter3).1 = voter3_entry.clone();
 
-        let checkpoint = voter_checkpoint(current_slot, vote_accounts, &mut voter_record);
-        assert_eq!(checkpoint.len(), (MAX_VOTE_DELAY + 1) as usize);
-        let mut expected_voters_set = HashSet::new();
-        expected_voters_set.insert(voter1.clone());
+        let checkpoint = voter_checkpoint(
+            current_slot,
+            &votes,
+            &mut voter_record,
+            DEFAULT_MAX_VOTE_DELAY,
+        );
+        assert_eq!(checkpoint.len(), (DEFAULT_MAX_VOTE_DELAY + 1) as usize);
         for (slot, voters) in checkpoint {
             // Expected voter 1 and voter 2 for the current_slot
             if slot == current_slot {
-                let mut expected_voters_set = expected_voters_set.clone();
-                expected_voters_set.insert(voter2.clone());
-                assert_eq!(voters, expected_voters_set);
+                assert_eq!(voters, voter_set(&voter_record, &[voter1, voter2]));
             // Expected only voter 1 for the other slots
             } else {
-                assert_eq!(voters, expected_voters_set);
+                assert_eq!(voters, voter_set(&voter_record, &[voter1]));
             }
         }
 
@@ -243,7 +423,6 @@ mod tests {
             voter_record.get(&voter1).unwrap(),
             &VoterEntry {
                 last_slot: current_slot,
-                last_hash: voter1_hash,
                 ..VoterEntry::default()
             }
         );
@@ -253,7 +432,6 @@ mod tests {
             voter_record.get(&voter2).unwrap(),
             &VoterEntry {
                 last_slot: current_slot,
-                last_hash: voter2_hash,
                 ..VoterEntry::default()
             }
         );
@@ -271,30 +449,15 @@ mod tests {
             Pubkey::new_rand(),
         ];
 
-        let low_latency_set = {
-            let mut set = HashSet::new();
-            for voter in &voters[..voters.len() - 1] {
-                set.insert(voter.clone());
-            }
-            set
-        };
-
-        let high_latency_set = {
-            let mut set = HashSet::new();
-            set.insert(voters[voters.len() - 1]);
-            set
-        };
+        let mut voter_record = VoterRecord::default();
+        for voter in voters.iter() {
+            voter_record.entry(voter);
+        }
 
+        let low_latency_set = voter_set(&voter_record, &voters[..voters.len() - 1]);
+        let high_latency_set = voter_set(&voter_record, &voters[voters.len() - 1..]);
         let voter_sets = vec![low_latency_set, high_latency_set];
 
-        let mut voter_record = {
-            let mut map = HashMap::new();
-            for voter in voters.iter() {
-                map.insert(voter.clone(), VoterEntry::default());
-            }
-            map
-        };
-
         score_voters(&voter_sets, &mut voter_record);
 
         for voter in &voters[..voters.len() - 1] {
@@ -308,79 +471,45 @@ mod tests {
     fn test_on_entry() {
         let current_slot = 100;
         let recent_slot = 99;
-        let old_slot = current_slot - MAX_VOTE_DELAY - 1;
-        let new_vote_account = |vote_range: Range<Slot>| -> Account {
-            let mut state = VoteState::default();
-            for vote_slot in vote_range {
-                state.votes.push_back(Lockout::new(vote_slot));
-            }
-            let owner = Pubkey::new_rand();
-            let mut account = Account::new_data(1, &state, &owner).unwrap();
-            account.hash = hash(owner.as_ref());
-            account
-        };
+        let old_slot = current_slot - DEFAULT_MAX_VOTE_DELAY - 1;
+        let new_votes = |vote_range: Range<Slot>| -> Vec<Slot> { vote_range.collect() };
 
         let voter1 = Pubkey::new_rand();
         let voter2 = Pubkey::new_rand();
         let voter3 = Pubkey::new_rand();
 
-        let mut vote_accounts = HashMap::new();
-        vote_accounts.insert(
-            voter1.clone(),
-            (0, new_vote_account(current_slot..current_slot + 1)),
-        );
-        vote_accounts.insert(
-            voter2.clone(),
-            (0, new_vote_account(current_slot..current_slot + 1)),
-        );
-        vote_accounts.insert(
-            voter3.clone(),
-            (0, new_vote_account(recent_slot..current_slot + 1)),
-        );
-
-        let recent_slot_first_voter_set = {
-            let mut set = HashSet::new();
-            set.insert(voter1.clone());
-            set.insert(voter2.clone());
-            set
-        };
-
-        let expected_recent_slot_second_voter_set = {
-            let mut set = HashSet::new();
-            set.insert(voter3.clone());
-            set
-        };
+        let votes = vec![
+            (voter1, new_votes(current_slot..current_slot + 1)),
+            (voter2, new_votes(current_slot..current_slot + 1)),
+            (voter3, new_votes(recent_slot..current_slot + 1)),
+        ];
 
-        let old_slot_voter_set = {
-            let mut set = HashSet::new();
-            set.insert(voter1.clone());
-            set
-        };
+        let mut voter_record = VoterRecord::default();
+        for voter in &[voter1, voter2, voter3] {
+            voter_record.entry(voter);
+        }
 
-        let expected_current_slot_voter_set = {
-            let mut set = HashSet::new();
-            set.insert(voter1.clone());
-            set.insert(voter2.clone());
-            set.insert(voter3.clone());
-            set
-        };
+        let recent_slot_first_voter_set = voter_set(&voter_record, &[voter1, voter2]);
+        let expected_recent_slot_second_voter_set = voter_set(&voter_record, &[voter3]);
+        let old_slot_voter_set = voter_set(&voter_record, &[voter1]);
+        let expected_current_slot_voter_set = voter_set(&voter_record, &[voter1, voter2, voter3]);
 
         let mut slot_voter_segments = BTreeMap::default();
         slot_voter_segments.insert(old_slot, vec![old_slot_voter_set]);
         slot_voter_segments.insert(recent_slot, vec![recent_slot_first_voter_set.clone()]);
 
-        let mut voter_record = HashMap::new();
         on_entry(
             current_slot,
-            vote_accounts,
+            &votes,
             &mut voter_record,
             &mut slot_voter_segments,
+            DEFAULT_MAX_VOTE_DELAY,
         );
         assert_eq!(slot_voter_segments.len(), 2);
 
         // Should periodically purge and score slot_voter_segments
         assert!(slot_voter_segments.get(&old_slot).is_none());
-        assert_eq!(voter_record[&voter1].latency_score, 1);
+        assert_eq!(voter_record.get(&voter1).unwrap().latency_score, 1);
 
         // Should push back a new voter set after latest checkpoint
         assert_eq!(
@@ -400,12 +529,11 @@ mod tests {
 
     #[test]
     fn test_validator_results() {
-        let new_vote_account = |validator_id: &Pubkey| -> Account {
-            let state = VoteState::new(&VoteInit {
-                node_pubkey: validator_id.clone(),
-                ..VoteInit::default()
-            });
-            Account::new_data(1, &state, &Pubkey::new_rand()).unwrap()
+        let new_vote_account = |validator_id: &Pubkey| -> VoteAccountState {
+            VoteAccountState {
+                node_pubkey: *validator_id,
+                ..VoteAccountState::default()
+            }
         };
 
         let validator1 = Pubkey::new_rand();
@@ -418,32 +546,14 @@ mod tests {
         let voter3 = Pubkey::new_rand();
 
         let mut vote_accounts = HashMap::new();
-        vote_accounts.insert(voter1.clone(), (0, new_vote_account(&validator1)));
-        vote_accounts.insert(voter2.clone(), (0, new_vote_account(&validator2)));
-        vote_accounts.insert(voter3.clone(), (0, new_vote_account(&baseline_validator)));
-
-        let mut voter_record = HashMap::new();
-        voter_record.insert(
-            voter1,
-            VoterEntry {
-                latency_score: 100,
-                ..VoterEntry::default()
-            },
-        );
-        voter_record.insert(
-            voter2,
-            VoterEntry {
-                latency_score: 200,
-                ..VoterEntry::default()
-            },
-        );
-        voter_record.insert(
-            voter3,
-            VoterEntry {
-                latency_score: 300,
-                ..VoterEntry::default()
-            },
-        );
+        vote_accounts.insert(voter1, new_vote_account(&validator1));
+        vote_accounts.insert(voter2, new_vote_account(&validator2));
+        vote_accounts.insert(voter3, new_vote_account(&baseline_validator));
+
+        let mut voter_record = VoterRecord::default();
+        voter_record.entry(&voter1).1.latency_score = 100;
+        voter_record.entry(&voter2).1.latency_score = 200;
+        voter_record.entry(&voter3).1.latency_score = 300;
 
         let excluded_set = {
             let mut set = HashSet::new();
@@ -455,12 +565,82 @@ mod tests {
         let (results, baseline) = validator_results(
             &baseline_validator,
             &excluded_set,
-            vote_accounts,
-            &mut voter_record,
-        );
+            &vote_accounts,
+            &voter_record,
+        )
+        .unwrap();
         assert_eq!(results.len(), 2);
         assert_eq!(results[0], (validator2, 200f64));
         assert_eq!(results[1], (validator1, 100f64));
         assert_eq!(baseline, 300f64);
+
+        // The baseline must have a vote account
+        vote_accounts.remove(&voter3);
+        match validator_results(
+            &baseline_validator,
+            &excluded_set,
+            &vote_accounts,
+            &voter_record,
+        ) {
+            Err(WinnerToolError::MissingBaseline { baseline, .. }) => {
+                assert_eq!(baseline, baseline_validator)
+            }
+            _ => panic!("expected a missing baseline error"),
+        }
+    }
+
+    #[test]
+    fn test_validator_points() {
+        let new_vote_account = |validator_id: &Pubkey| -> VoteAccountState {
+            VoteAccountState {
+                node_pubkey: *validator_id,
+                ..VoteAccountState::default()
+            }
+        };
+
+        let validator = Pubkey::new_rand();
+        let voter1 = Pubkey::new_rand();
+        let voter2 = Pubkey::new_rand();
+        let unknown_voter = Pubkey::new_rand();
+        let mut vote_accounts = HashMap::new();
+        vote_accounts.insert(voter1, new_vote_account(&validator));
+        vote_accounts.insert(voter2, new_vote_account(&validator));
+
+        let mut voter_points = HashMap::new();
+        voter_points.insert(voter1, -2);
+        voter_points.insert(voter2, 3);
+        voter_points.insert(unknown_voter, 9);
+
+        let points = validator_points(&vote_accounts, voter_points);
+        assert_eq!(points.len(), 1);
+        assert_eq!(points[&validator], Score::Points(3));
+    }
+
+    #[test]
+    fn test_window_voter_record() {
+        let voter = Pubkey::new_rand();
+        let new_voter = Pubkey::new_rand();
+        let mut vote_accounts = HashMap::new();
+        vote_accounts.insert(
+            voter,
+            VoteAccountState {
+                last_vote_slot: Some(42),
+                ..VoteAccountState::default()
+            },
+        );
+        vote_accounts.insert(new_voter, VoteAccountState::default());
+
+        let voter_record = window_voter_record(&vote_accounts);
+        assert_eq!(
+            voter_record.get(&voter).unwrap(),
+            &VoterEntry {
+                latency_score: 0,
+                last_slot: 42,
+            }
+        );
+        assert_eq!(
+            voter_record.get(&new_voter).unwrap(),
+            &VoterEntry::default()
+        );
     }
 }
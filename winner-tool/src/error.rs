@@ -0,0 +1,164 @@
+//! Errors of a scoring run. Each error describes what went wrong in terms of the inputs of the
+//! run, and maps to the exit code of the CLI so that scripts can tell the failures apart.
+
+use solana_sdk::{clock::Slot, hash::Hash, pubkey::Pubkey};
+use std::{error, fmt};
+
+#[derive(Debug)]
+pub enum WinnerToolError {
+    /// An argument or parameter of the run is invalid
+    InvalidArgument(String),
+    /// A config, exclusion or pubkey map file cannot be read or parsed
+    Config(String),
+    /// The ledger cannot be opened or replayed
+    Ledger(String),
+    /// A slot on the scored chain of blocks has no metadata in the blockstore
+    MissingSlot(Slot),
+    /// The leader schedule of the ledger does not cover a slot of the window
+    MissingLeader(Slot),
+    /// The replayed bank at a slot does not match its expected hash
+    BankHashMismatch {
+        slot: Slot,
+        expected: Hash,
+        replayed: Hash,
+    },
+    /// The baseline validator has no `measurement` in the scored window
+    MissingBaseline {
+        baseline: Pubkey,
+        measurement: &'static str,
+    },
+    /// No validator could be ranked in the named category
+    NoResults(&'static str),
+    /// A checkpoint or snapshot archive cannot be saved or restored
+    Checkpoint(String),
+    /// An event log cannot be written or read
+    EventLog(String),
+    /// A category panicked while observing the ledger
+    ObserverFailed,
+    /// A server cannot listen on its address
+    Server(String),
+    /// The pre-flight checks of the run found these errors
+    Preflight(Vec<String>),
+}
+
+impl WinnerToolError {
+    /// Exit code of the CLI when a run fails with this error
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            WinnerToolError::ObserverFailed => 1,
+            WinnerToolError::InvalidArgument(_)
+            | WinnerToolError::Config(_)
+            | WinnerToolError::Preflight(_) => 2,
+            WinnerToolError::Ledger(_)
+            | WinnerToolError::MissingSlot(_)
+            | WinnerToolError::MissingLeader(_)
+            | WinnerToolError::BankHashMismatch { .. } => 3,
+            WinnerToolError::MissingBaseline { .. } | WinnerToolError::NoResults(_) => 4,
+            WinnerToolError::Checkpoint(_) => 5,
+            WinnerToolError::EventLog(_) => 6,
+            WinnerToolError::Server(_) => 7,
+        }
+    }
+}
+
+impl fmt::Display for WinnerToolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WinnerToolError::InvalidArgument(message)
+            | WinnerToolError::Config(message)
+            | WinnerToolError::Ledger(message)
+            | WinnerToolError::Checkpoint(message)
+            | WinnerToolError::EventLog(message)
+            | WinnerToolError::Server(message) => write!(f, "{}", message),
+            WinnerToolError::MissingSlot(slot) => write!(
+                f,
+                "Slot {} of the scored chain is missing from the ledger; the ledger is \
+                 incomplete, copy it again from a validator that holds every slot of the window",
+                slot
+            ),
+            WinnerToolError::MissingLeader(slot) => write!(
+                f,
+                "The leader schedule of the ledger does not cover slot {}; the ledger may have \
+                 been processed with a different genesis config",
+                slot
+            ),
+            WinnerToolError::BankHashMismatch {
+                slot,
+                expected,
+                replayed,
+            } => write!(
+                f,
+                "The replayed bank of slot {} has hash {} instead of the expected {}; the ledger \
+                 is not on the canonical chain, or was replayed by a different release of Solana",
+                slot, replayed, expected
+            ),
+            WinnerToolError::MissingBaseline {
+                baseline,
+                measurement,
+            } => write!(
+                f,
+                "Baseline validator {} has no {} in the scored window; check \
+                 --baseline-validator or baseline_validator in --config",
+                baseline, measurement
+            ),
+            WinnerToolError::NoResults(category) => write!(
+                f,
+                "No validator could be ranked in the {} category; check that the window \
+                 contains validators that are not excluded",
+                category
+            ),
+            WinnerToolError::ObserverFailed => write!(f, "Categories failed to observe the ledger"),
+            WinnerToolError::Preflight(errors) => {
+                write!(f, "Pre-flight checks failed:")?;
+                for error in errors {
+                    write!(f, "\n  - {}", error)?;
+                }
+                write!(f, "\nFix them, or pass --skip-preflight to replay anyway")
+            }
+        }
+    }
+}
+
+impl error::Error for WinnerToolError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let baseline = Pubkey::new_rand();
+        let err = WinnerToolError::MissingBaseline {
+            baseline,
+            measurement: "vote credits",
+        };
+        assert!(err.to_string().starts_with(&format!(
+            "Baseline validator {} has no vote credits",
+            baseline
+        )));
+        assert_eq!(
+            WinnerToolError::Config("Unable to open config.yml".to_string()).to_string(),
+            "Unable to open config.yml"
+        );
+    }
+
+    #[test]
+    fn test_exit_code() {
+        assert_eq!(
+            WinnerToolError::InvalidArgument(String::new()).exit_code(),
+            2
+        );
+        assert_eq!(WinnerToolError::MissingSlot(5).exit_code(), 3);
+        assert_eq!(
+            WinnerToolError::BankHashMismatch {
+                slot: 5,
+                expected: Hash::default(),
+                replayed: Hash::default(),
+            }
+            .exit_code(),
+            3
+        );
+        assert_eq!(WinnerToolError::NoResults("availability").exit_code(), 4);
+        assert_eq!(WinnerToolError::EventLog(String::new()).exit_code(), 6);
+    }
+}
@@ -0,0 +1,234 @@
+//! Compact binary log of the events observed while replaying a ledger, so that the ledger can be
+//! replayed once and scored many times. Vote accounts are written out once and then referred to
+//! by their index in the log.
+
+use crate::error::WinnerToolError;
+use crate::events::{AccountsState, EntryVotes, Event, LedgerSummary};
+use serde::{Deserialize, Serialize};
+use solana_sdk::{clock::Slot, pubkey::Pubkey};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+const EVENT_LOG_VERSION: u32 = 2;
+
+#[derive(Deserialize, Serialize)]
+enum Record {
+    Version(u32),
+    /// Assigns the next index to a vote account
+    Voter(Pubkey),
+    WindowStart(AccountsState),
+    Entry(Slot, Vec<(u32, Vec<Slot>)>),
+    EpochStart(AccountsState),
+    /// Last record of a complete log
+    Summary(LedgerSummary),
+}
+
+/// Writes the events observed during replay to an event log
+pub struct EventLogWriter {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    voter_indexes: HashMap<Pubkey, u32>,
+    // Replay cannot be interrupted by a failed write, so the first error is reported by `finish`
+    error: Option<String>,
+}
+
+impl EventLogWriter {
+    pub fn create(path: &Path) -> Result<Self, WinnerToolError> {
+        let file = File::create(path).map_err(|err| {
+            WinnerToolError::EventLog(format!("Unable to create {}: {}", path.display(), err))
+        })?;
+        let mut writer = Self {
+            path: path.to_path_buf(),
+            writer: BufWriter::new(file),
+            voter_indexes: HashMap::new(),
+            error: None,
+        };
+        writer.write_record(&Record::Version(EVENT_LOG_VERSION));
+        Ok(writer)
+    }
+
+    fn write_record(&mut self, record: &Record) {
+        if self.error.is_none() {
+            if let Err(err) = bincode::serialize_into(&mut self.writer, record) {
+                self.error = Some(format!("Unable to write {}: {}", self.path.display(), err));
+            }
+        }
+    }
+
+    fn voter_index(&mut self, voter_key: &Pubkey) -> u32 {
+        if let Some(index) = self.voter_indexes.get(voter_key) {
+            return *index;
+        }
+        let index = self.voter_indexes.len() as u32;
+        self.voter_indexes.insert(*voter_key, index);
+        self.write_record(&Record::Voter(*voter_key));
+        index
+    }
+
+    pub fn write(&mut self, event: &Event) {
+        let record = match event {
+            Event::WindowStart(accounts) => Record::WindowStart(accounts.clone()),
+            Event::Entry(EntryVotes { slot, votes }) => {
+                let votes = votes
+                    .iter()
+                    .map(|(voter_key, slots)| (self.voter_index(voter_key), slots.clone()))
+                    .collect();
+                Record::Entry(*slot, votes)
+            }
+            Event::EpochStart(accounts) => Record::EpochStart(accounts.clone()),
+        };
+        self.write_record(&record);
+    }
+
+    /// Completes the log with the state of the ledger once replay has finished
+    pub fn finish(mut self, summary: &LedgerSummary) -> Result<(), WinnerToolError> {
+        self.write_record(&Record::Summary(summary.clone()));
+        if self.error.is_none() {
+            if let Err(err) = self.writer.flush() {
+                self.error = Some(format!("Unable to write {}: {}", self.path.display(), err));
+            }
+        }
+        match self.error {
+            Some(err) => Err(WinnerToolError::EventLog(err)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Reads the event log at `path`, passing every event to `observe` in the order it was recorded.
+/// Returns the state of the ledger at the end of the log.
+pub fn read<F>(path: &Path, mut observe: F) -> Result<LedgerSummary, WinnerToolError>
+where
+    F: FnMut(Event),
+{
+    let corrupt = || WinnerToolError::EventLog(format!("Event log {} is corrupt", path.display()));
+    let file = File::open(path).map_err(|err| {
+        WinnerToolError::EventLog(format!("Unable to open {}: {}", path.display(), err))
+    })?;
+    let mut reader = BufReader::new(file);
+    let mut voters = vec![];
+    let mut version = None;
+    loop {
+        let record: Record = bincode::deserialize_from(&mut reader).map_err(|err| {
+            WinnerToolError::EventLog(match *err {
+                bincode::ErrorKind::Io(ref io_err)
+                    if io_err.kind() == io::ErrorKind::UnexpectedEof =>
+                {
+                    format!(
+                        "Event log {} is incomplete; record it again",
+                        path.display()
+                    )
+                }
+                _ => format!("Unable to read {}: {}", path.display(), err),
+            })
+        })?;
+
+        let event = match (version, record) {
+            (None, Record::Version(EVENT_LOG_VERSION)) => {
+                version = Some(EVENT_LOG_VERSION);
+                continue;
+            }
+            (None, _) => {
+                return Err(WinnerToolError::EventLog(format!(
+                    "{} is not an event log of version {}",
+                    path.display(),
+                    EVENT_LOG_VERSION
+                )))
+            }
+            (Some(_), Record::Version(_)) => return Err(corrupt()),
+            (Some(_), Record::Voter(voter_key)) => {
+                voters.push(voter_key);
+                continue;
+            }
+            (Some(_), Record::WindowStart(accounts)) => Event::WindowStart(accounts),
+            (Some(_), Record::Entry(slot, votes)) => {
+                let votes = votes
+                    .into_iter()
+                    .map(|(index, slots)| {
+                        voters
+                            .get(index as usize)
+                            .map(|voter_key| (*voter_key, slots))
+                            .ok_or_else(corrupt)
+                    })
+                    .collect::<Result<_, WinnerToolError>>()?;
+                Event::Entry(EntryVotes { slot, votes })
+            }
+            (Some(_), Record::EpochStart(accounts)) => Event::EpochStart(accounts),
+            (Some(_), Record::Summary(summary)) => return Ok(summary),
+        };
+        observe(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::{LeaderSlots, VoteAccountState};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_round_trip() {
+        let voter1 = Pubkey::new_rand();
+        let voter2 = Pubkey::new_rand();
+        let mut accounts = AccountsState {
+            slot: 10,
+            epoch: 1,
+            block_height: 8,
+            ..AccountsState::default()
+        };
+        accounts.vote_accounts.insert(
+            voter1,
+            VoteAccountState {
+                node_pubkey: Pubkey::new_rand(),
+                credits: 5,
+                ..VoteAccountState::default()
+            },
+        );
+        let events = vec![
+            Event::WindowStart(accounts.clone()),
+            Event::Entry(EntryVotes {
+                slot: 10,
+                votes: vec![(voter1, vec![8, 9]), (voter2, vec![9])],
+            }),
+            Event::EpochStart(accounts.clone()),
+            Event::Entry(EntryVotes {
+                slot: 11,
+                votes: vec![(voter2, vec![10])],
+            }),
+        ];
+        let mut leader_slots = LeaderSlots::default();
+        leader_slots.push(10, 1, &voter1, true);
+        let summary = LedgerSummary {
+            first_slot: 10,
+            accounts,
+            leader_slots,
+        };
+
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("events.bin");
+        let mut writer = EventLogWriter::create(&path).unwrap();
+        for event in &events {
+            writer.write(event);
+        }
+        writer.finish(&summary).unwrap();
+
+        let mut read_events = vec![];
+        let read_summary = read(&path, |event| read_events.push(event)).unwrap();
+        assert_eq!(read_events, events);
+        assert_eq!(read_summary, summary);
+    }
+
+    #[test]
+    fn test_incomplete_log() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("events.bin");
+        let mut writer = EventLogWriter::create(&path).unwrap();
+        writer.write(&Event::Entry(EntryVotes::default()));
+        writer.writer.flush().unwrap();
+
+        assert!(read(&path, |_| {}).is_err());
+        assert!(read(&temp_dir.path().join("missing.bin"), |_| {}).is_err());
+    }
+}
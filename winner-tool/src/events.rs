@@ -0,0 +1,413 @@
+//! Observations of the ledger that the categories are scored from. Replay turns every bank it
+//! processes into a stream of events, which the categories either observe as they are produced or
+//! read back from an event log.
+
+use crate::error::WinnerToolError;
+use crate::key_index::KeyIndex;
+use crate::utils;
+use serde::{Deserialize, Serialize};
+use solana_ledger::{blockstore::Blockstore, leader_schedule_cache::LeaderScheduleCache};
+use solana_runtime::bank::Bank;
+use solana_sdk::{
+    account::Account,
+    clock::{Epoch, Slot},
+    hash::Hash,
+    pubkey::Pubkey,
+};
+use solana_stake_program::stake_state::Delegation;
+use solana_vote_program::vote_state::VoteState;
+use std::cmp::max;
+use std::collections::{HashMap, HashSet};
+
+/// State of a vote account that the categories are scored from
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct VoteAccountState {
+    pub node_pubkey: Pubkey,
+    /// Active stake delegated to the vote account
+    pub stake: u64,
+    /// Stake of every delegation to the vote account, including rewards
+    pub delegated_stake: u64,
+    /// Balance of the vote account, made up of commission
+    pub lamports: u64,
+    pub credits: u64,
+    pub epoch_credits: Vec<(Epoch, u64, u64)>,
+    pub last_vote_slot: Option<Slot>,
+}
+
+/// State of the vote accounts of a bank
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct AccountsState {
+    pub slot: Slot,
+    pub epoch: Epoch,
+    pub block_height: u64,
+    pub vote_accounts: HashMap<Pubkey, VoteAccountState>,
+}
+
+impl AccountsState {
+    pub fn new(bank: &Bank) -> Self {
+        let delegated_stakes = voter_stake_rewards(bank.stake_delegations());
+        let vote_accounts = bank
+            .vote_accounts()
+            .into_iter()
+            .filter_map(|(voter_key, (stake, account))| {
+                let vote_state = VoteState::from(&account)?;
+                let vote_account = VoteAccountState {
+                    node_pubkey: vote_state.node_pubkey,
+                    stake,
+                    delegated_stake: delegated_stakes.get(&voter_key).cloned().unwrap_or(0),
+                    lamports: account.lamports,
+                    credits: vote_state.credits(),
+                    epoch_credits: vote_state.epoch_credits().cloned().collect(),
+                    last_vote_slot: vote_state.votes.back().map(|vote| vote.slot),
+                };
+                Some((voter_key, vote_account))
+            })
+            .collect();
+
+        Self {
+            slot: bank.slot(),
+            epoch: bank.epoch(),
+            block_height: bank.block_height(),
+            vote_accounts,
+        }
+    }
+}
+
+fn voter_stake_rewards(stake_delegations: HashMap<Pubkey, Delegation>) -> HashMap<Pubkey, u64> {
+    let mut voter_stake_sum: HashMap<Pubkey, u64> = HashMap::new();
+    for (_key, delegation) in stake_delegations {
+        voter_stake_sum
+            .entry(delegation.voter_pubkey)
+            .and_modify(|stake_sum| *stake_sum += delegation.stake)
+            .or_insert(delegation.stake);
+    }
+    voter_stake_sum
+}
+
+/// Votes that landed in vote accounts while an entry was processed
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct EntryVotes {
+    /// Slot of the bank the entry was processed in
+    pub slot: Slot,
+    /// Slots that each vote account voted on for the first time, oldest first
+    pub votes: Vec<(Pubkey, Vec<Slot>)>,
+}
+
+/// Leader of a slot and whether it produced a block on the fork of the final bank
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct LeaderSlot {
+    pub slot: Slot,
+    pub epoch: Epoch,
+    /// Index of the leader in `LeaderSlots::leaders`
+    pub leader: u32,
+    pub produced: bool,
+}
+
+/// Leader of every slot in the window. Leaders are interned, so that each slot only stores the
+/// index of its leader.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct LeaderSlots {
+    pub leaders: KeyIndex,
+    /// Leader slots in ascending order
+    pub slots: Vec<LeaderSlot>,
+}
+
+impl LeaderSlots {
+    pub fn push(&mut self, slot: Slot, epoch: Epoch, leader: &Pubkey, produced: bool) {
+        let leader = self.leaders.insert(leader);
+        self.slots.push(LeaderSlot {
+            slot,
+            epoch,
+            leader,
+            produced,
+        });
+    }
+
+    /// Identity of the leader of `leader_slot`
+    pub fn leader(&self, leader_slot: &LeaderSlot) -> &Pubkey {
+        self.leaders.key(leader_slot.leader)
+    }
+}
+
+/// Leader of every slot from `first_slot` up to the bank's slot
+pub fn leader_slots(
+    bank: &Bank,
+    blockstore: &Blockstore,
+    leader_schedule_cache: &LeaderScheduleCache,
+    first_slot: Slot,
+) -> Result<LeaderSlots, WinnerToolError> {
+    let block_chain: HashSet<Slot> = utils::block_chain(first_slot, bank.slot(), blockstore)?
+        .into_iter()
+        .collect();
+
+    let mut leader_slots = LeaderSlots::default();
+    // The genesis block has no leader
+    for slot in max(first_slot, 1)..=bank.slot() {
+        let leader = leader_schedule_cache
+            .slot_leader_at(slot, Some(bank))
+            .ok_or(WinnerToolError::MissingLeader(slot))?;
+        leader_slots.push(
+            slot,
+            bank.epoch_schedule().get_epoch(slot),
+            &leader,
+            block_chain.contains(&slot),
+        );
+    }
+    Ok(leader_slots)
+}
+
+/// Observation of the ledger during replay
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    /// State of the accounts at the start of the scoring window, when the window does not start at
+    /// genesis
+    WindowStart(AccountsState),
+    /// Votes observed after an entry within the window was processed
+    Entry(EntryVotes),
+    /// State of the accounts when replay first reaches an epoch within the window
+    EpochStart(AccountsState),
+}
+
+/// State of the ledger once replay has finished
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct LedgerSummary {
+    /// First slot of the scored window
+    pub first_slot: Slot,
+    /// State of the accounts of the final bank
+    pub accounts: AccountsState,
+    /// Leader of every slot in the window
+    pub leader_slots: LeaderSlots,
+}
+
+// Tracks the latest vote of each vote account in `touched_accounts`, returning the slots voted on
+// since the last checkpoint. Only vote accounts whose hash changed are deserialized.
+fn new_votes(
+    touched_accounts: Vec<(Pubkey, Account)>,
+    last_votes: &mut HashMap<Pubkey, (Hash, Slot)>,
+) -> Vec<(Pubkey, Vec<Slot>)> {
+    let mut new_votes = vec![];
+    for (voter_key, account) in touched_accounts {
+        let (last_hash, last_slot) = last_votes.entry(voter_key).or_default();
+        if *last_hash == account.hash {
+            continue;
+        }
+        *last_hash = account.hash;
+        if let Some(vote_state) = VoteState::from(&account) {
+            let slots: Vec<Slot> = vote_state
+                .votes
+                .iter()
+                .map(|lockout| lockout.slot)
+                .filter(|slot| *slot > *last_slot)
+                .collect();
+            if let Some(slot) = slots.last() {
+                *last_slot = *slot;
+                new_votes.push((voter_key, slots));
+            }
+        }
+    }
+    new_votes
+}
+
+/// Turns the banks processed during replay into events
+pub struct Recorder {
+    start_slot: Option<Slot>,
+    window_started: bool,
+    last_slot: Option<Slot>,
+    last_epoch: Option<Epoch>,
+    last_votes: HashMap<Pubkey, (Hash, Slot)>,
+}
+
+impl Recorder {
+    /// Recorder for a window that starts at `start_slot`, or at genesis
+    pub fn new(start_slot: Option<Slot>) -> Self {
+        Self {
+            start_slot,
+            window_started: false,
+            last_slot: None,
+            last_epoch: None,
+            last_votes: HashMap::new(),
+        }
+    }
+
+    pub fn window_started(&self) -> bool {
+        self.window_started
+    }
+
+    /// Continues the window of a checkpointed replay. Votes that landed before the checkpoint may
+    /// be observed again, which categories ignore like any vote they have already seen.
+    pub fn resume(&mut self, window_started: bool) {
+        self.window_started = window_started;
+    }
+
+    /// Starts the window at `bank`
+    pub fn start_window(&mut self, bank: &Bank) -> Event {
+        let accounts = AccountsState::new(bank);
+        self.window_started = true;
+        self.last_votes = bank
+            .vote_accounts()
+            .into_iter()
+            .map(|(voter_key, (_stake, account))| {
+                let last_slot = accounts
+                    .vote_accounts
+                    .get(&voter_key)
+                    .and_then(|vote_account| vote_account.last_vote_slot)
+                    .unwrap_or(0);
+                (voter_key, (account.hash, last_slot))
+            })
+            .collect();
+        Event::WindowStart(accounts)
+    }
+
+    /// Observes the working bank after a batch of entries has been processed
+    pub fn observe(&mut self, bank: &Bank) -> Vec<Event> {
+        let mut events = vec![];
+        let slot = bank.slot();
+        if let Some(start_slot) = self.start_slot {
+            if slot < start_slot {
+                return events;
+            }
+            if !self.window_started {
+                events.push(self.start_window(bank));
+            }
+        }
+
+        // Only the vote accounts that transactions of the slot have stored so far can have new
+        // votes, so the rest of the vote accounts are neither cloned nor deserialized.
+        //
+        // The accounts stored by earlier batches of the slot are returned again for every batch,
+        // since v0.22 does not expose their write versions, so the work per slot grows with the
+        // number of batches times the number of voters. `new_votes` skips the accounts whose hash
+        // has not changed, which leaves cloning them as the repeated cost.
+        let touched_accounts =
+            bank.get_program_accounts_modified_since_parent(&solana_vote_program::id());
+        let votes = new_votes(touched_accounts, &mut self.last_votes);
+
+        // Entries of the same slot without new votes are left out, they make no difference
+        if !votes.is_empty() || self.last_slot != Some(slot) {
+            self.last_slot = Some(slot);
+            events.push(Event::Entry(EntryVotes { slot, votes }));
+        }
+
+        if self
+            .last_epoch
+            .map(|epoch| epoch < bank.epoch())
+            .unwrap_or(true)
+        {
+            self.last_epoch = Some(bank.epoch());
+            events.push(Event::EpochStart(AccountsState::new(bank)));
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::hash::hash;
+    use solana_vote_program::vote_state::Lockout;
+    use std::ops::Range;
+
+    #[test]
+    fn test_voter_stake_rewards() {
+        let new_stake_delegation = |stake: u64, voter_pubkey: &Pubkey| -> Delegation {
+            Delegation {
+                voter_pubkey: *voter_pubkey,
+                stake,
+                ..Delegation::default()
+            }
+        };
+
+        let voter_pubkey1 = Pubkey::new_rand();
+        let voter_pubkey2 = Pubkey::new_rand();
+
+        let mut stake_accounts = HashMap::new();
+        stake_accounts.insert(
+            Pubkey::new_rand(),
+            new_stake_delegation(100, &voter_pubkey1),
+        );
+        stake_accounts.insert(
+            Pubkey::new_rand(),
+            new_stake_delegation(100, &voter_pubkey2),
+        );
+        stake_accounts.insert(
+            Pubkey::new_rand(),
+            new_stake_delegation(100, &voter_pubkey2),
+        );
+
+        let expected = {
+            let mut map = HashMap::new();
+            map.insert(voter_pubkey1, 100);
+            map.insert(voter_pubkey2, 200);
+            map
+        };
+
+        assert_eq!(expected, voter_stake_rewards(stake_accounts));
+    }
+
+    #[test]
+    fn test_new_votes() {
+        let new_vote_account = |vote_range: Range<Slot>| -> Account {
+            let mut state = VoteState::default();
+            for vote_slot in vote_range {
+                state.votes.push_back(Lockout::new(vote_slot));
+            }
+            let owner = Pubkey::new_rand();
+            let mut account = Account::new_data(1, &state, &owner).unwrap();
+            account.hash = hash(owner.as_ref());
+            account
+        };
+
+        let new_voter = Pubkey::new_rand();
+        let voter = Pubkey::new_rand();
+        let unchanged_voter = Pubkey::new_rand();
+
+        let unchanged_account = new_vote_account(5..10);
+        let unchanged_hash = unchanged_account.hash;
+        let touched_accounts = vec![
+            (new_voter, new_vote_account(1..4)),
+            (voter, new_vote_account(5..10)),
+            (unchanged_voter, unchanged_account),
+        ];
+
+        let mut last_votes = HashMap::new();
+        last_votes.insert(voter, (Hash::default(), 7));
+        last_votes.insert(unchanged_voter, (unchanged_hash, 7));
+
+        let votes = new_votes(touched_accounts, &mut last_votes);
+        assert_eq!(votes, vec![(new_voter, vec![1, 2, 3]), (voter, vec![8, 9])]);
+
+        // Every vote account should track its latest vote
+        assert_eq!(last_votes[&new_voter].1, 3);
+        assert_eq!(last_votes[&voter].1, 9);
+        assert_eq!(last_votes[&unchanged_voter], (unchanged_hash, 7));
+    }
+
+    #[test]
+    fn test_new_votes_stored_twice_in_slot() {
+        let new_vote_account = |vote_range: Range<Slot>| -> Account {
+            let mut state = VoteState::default();
+            for vote_slot in vote_range.clone() {
+                state.votes.push_back(Lockout::new(vote_slot));
+            }
+            let mut account = Account::new_data(1, &state, &Pubkey::new_rand()).unwrap();
+            account.hash = hash(&vote_range.end.to_le_bytes());
+            account
+        };
+
+        let voter = Pubkey::new_rand();
+        let mut last_votes = HashMap::new();
+
+        // The first batch of the slot stores the vote account
+        let votes = new_votes(vec![(voter, new_vote_account(1..4))], &mut last_votes);
+        assert_eq!(votes, vec![(voter, vec![1, 2, 3])]);
+
+        // A later batch of the same slot returns the account again without a new store
+        let votes = new_votes(vec![(voter, new_vote_account(1..4))], &mut last_votes);
+        assert!(votes.is_empty());
+
+        // Another vote stored in the same slot only yields the newly voted slot
+        let votes = new_votes(vec![(voter, new_vote_account(1..5))], &mut last_votes);
+        assert_eq!(votes, vec![(voter, vec![4])]);
+        assert_eq!(last_votes[&voter].1, 4);
+    }
+}
@@ -0,0 +1,148 @@
+//! Dense indexes of public keys, so that per-slot records of the ledger can refer to vote accounts
+//! and leaders by a small index and track sets of them as bitsets.
+
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashMap;
+
+/// Assigns consecutive indexes to public keys in the order they are first seen
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(from = "Vec<Pubkey>", into = "Vec<Pubkey>")]
+pub struct KeyIndex {
+    keys: Vec<Pubkey>,
+    indexes: HashMap<Pubkey, u32>,
+}
+
+impl KeyIndex {
+    /// Index of `key`, which is assigned the next index if it has not been seen before
+    pub fn insert(&mut self, key: &Pubkey) -> u32 {
+        if let Some(index) = self.indexes.get(key) {
+            return *index;
+        }
+        let index = self.keys.len() as u32;
+        self.keys.push(*key);
+        self.indexes.insert(*key, index);
+        index
+    }
+
+    pub fn get(&self, key: &Pubkey) -> Option<u32> {
+        self.indexes.get(key).cloned()
+    }
+
+    pub fn key(&self, index: u32) -> &Pubkey {
+        &self.keys[index as usize]
+    }
+
+    pub fn keys(&self) -> &[Pubkey] {
+        &self.keys
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+}
+
+impl From<Vec<Pubkey>> for KeyIndex {
+    fn from(keys: Vec<Pubkey>) -> Self {
+        let indexes = keys
+            .iter()
+            .enumerate()
+            .map(|(index, key)| (*key, index as u32))
+            .collect();
+        Self { keys, indexes }
+    }
+}
+
+impl From<KeyIndex> for Vec<Pubkey> {
+    fn from(key_index: KeyIndex) -> Self {
+        key_index.keys
+    }
+}
+
+/// Set of indexes of a `KeyIndex`, one bit per index
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct IndexSet {
+    words: Vec<u64>,
+}
+
+impl IndexSet {
+    pub fn insert(&mut self, index: u32) {
+        let word = index as usize / 64;
+        if word >= self.words.len() {
+            self.words.resize(word + 1, 0);
+        }
+        self.words[word] |= 1 << (index % 64);
+    }
+
+    pub fn contains(&self, index: u32) -> bool {
+        self.words
+            .get(index as usize / 64)
+            .map(|word| word & (1 << (index % 64)) != 0)
+            .unwrap_or(false)
+    }
+
+    pub fn len(&self) -> usize {
+        self.words
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|word| *word == 0)
+    }
+
+    /// Indexes in the set, in ascending order
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = u32> + 'a {
+        self.words
+            .iter()
+            .enumerate()
+            .flat_map(|(word_index, word)| {
+                (0..64)
+                    .filter(move |bit| word & (1 << bit) != 0)
+                    .map(move |bit| (word_index * 64) as u32 + bit)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_index() {
+        let key1 = Pubkey::new_rand();
+        let key2 = Pubkey::new_rand();
+        let mut key_index = KeyIndex::default();
+        assert_eq!(key_index.insert(&key1), 0);
+        assert_eq!(key_index.insert(&key2), 1);
+        assert_eq!(key_index.insert(&key1), 0);
+        assert_eq!(key_index.get(&key2), Some(1));
+        assert_eq!(key_index.get(&Pubkey::new_rand()), None);
+        assert_eq!(key_index.key(1), &key2);
+        assert_eq!(key_index.len(), 2);
+
+        // Only the keys are serialized, the indexes are rebuilt from their order
+        let serialized = bincode::serialize(&key_index).unwrap();
+        let deserialized: KeyIndex = bincode::deserialize(&serialized).unwrap();
+        assert_eq!(deserialized, key_index);
+    }
+
+    #[test]
+    fn test_index_set() {
+        let mut index_set = IndexSet::default();
+        assert!(index_set.is_empty());
+        for index in &[130, 3, 64, 3] {
+            index_set.insert(*index);
+        }
+        assert_eq!(index_set.len(), 3);
+        assert!(index_set.contains(64));
+        assert!(!index_set.contains(65));
+        assert!(!index_set.contains(1000));
+        assert_eq!(index_set.iter().collect::<Vec<_>>(), vec![3, 64, 130]);
+    }
+}
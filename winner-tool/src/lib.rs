@@ -0,0 +1,38 @@
+//! Calculates the quantitative category winners for Tour de SOL.
+//!
+//! `ScoringSession` replays a ledger and evaluates the enabled categories. Categories implement
+//! `ScoringCategory` and are enabled by name through a `CategoryRegistry`, so new categories can
+//! be prototyped without touching the replay loop. Categories observe the ledger through the
+//! events of the `events` module, which can be recorded to an event log and scored again later
+//! without replaying the ledger. The scoring modules are public so that other tools can reuse
+//! individual parts of the calculation.
+
+pub mod audit;
+pub mod availability;
+pub mod bank_hash;
+pub mod category;
+pub mod checkpoint;
+pub mod config;
+pub mod confirmation_latency;
+pub mod error;
+pub mod event_log;
+pub mod events;
+pub mod key_index;
+pub mod merge;
+pub mod metrics;
+pub mod page;
+pub mod preflight;
+pub mod progress;
+pub mod report;
+pub mod rewards_earned;
+pub mod server;
+pub mod session;
+pub mod utils;
+pub mod winner;
+
+pub use category::{CategoryRegistry, ScoringCategory};
+pub use error::WinnerToolError;
+pub use session::{
+    score_event_log, watch_ledger, ScoringOptions, ScoringParameters, ScoringResults,
+    ScoringSession, WatchOptions,
+};
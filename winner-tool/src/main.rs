@@ -3,54 +3,199 @@
 //! NOTE: Ledger processing uses native programs, so this tool must be invoked with `cargo run`.
 //! If installed with `cargo install` the native programs may not be linked properly.
 
-mod availability;
-mod confirmation_latency;
-mod rewards_earned;
-mod utils;
-mod winner;
-
 use clap::{
     crate_description, crate_name, crate_version, value_t, value_t_or_exit, values_t_or_exit, App,
-    Arg,
+    Arg, ArgMatches, SubCommand,
 };
-use confirmation_latency::{SlotVoterSegments, VoterRecord};
 use solana_clap_utils::{
     input_parsers::pubkey_of,
     input_validators::{is_pubkey, is_pubkey_or_keypair},
 };
-use solana_ledger::{
-    blockstore::Blockstore,
-    blockstore_processor::{process_blockstore, ProcessOptions},
-};
-use solana_runtime::bank::Bank;
+use solana_ledger::blockstore::Blockstore;
 use solana_sdk::{
+    clock::Slot,
     genesis_config::GenesisConfig,
     native_token::{lamports_to_sol, sol_to_lamports},
     pubkey::Pubkey,
 };
+use solana_tds_winner_tool::{
+    audit,
+    bank_hash::parse_expected_bank_hash,
+    checkpoint,
+    config::{self, Config},
+    merge,
+    metrics::Metrics,
+    page,
+    preflight::{self, PreflightReport, Severity},
+    report, score_event_log,
+    server::{self, Leaderboard, SharedLeaderboard},
+    session::WatchedLedger,
+    watch_ledger, winner, CategoryRegistry, ScoringOptions, ScoringResults, ScoringSession,
+    WatchOptions, WinnerToolError,
+};
 use std::{
     collections::{HashMap, HashSet},
-    fs,
-    path::PathBuf,
+    net::TcpListener,
+    path::{Path, PathBuf},
     process::exit,
+    str::FromStr,
     sync::{Arc, RwLock},
+    thread,
+    time::{Duration, Instant},
 };
 
 const PUBKEY_MAP_FILE: &str = "validators/all-username.yml";
 
+fn is_parsable<T: FromStr>(value: String) -> Result<(), String> {
+    value
+        .parse::<T>()
+        .map(|_| ())
+        .map_err(|_| format!("Unable to parse {}", value))
+}
+
+// Parses a comma-separated list of three values, e.g. `0.95,0.75,0.5`
+fn parse_triple<T: Copy + Default + FromStr>(value: &str) -> Result<[T; 3], String> {
+    let values: Vec<T> = value
+        .split(',')
+        .map(|value| value.trim().parse::<T>())
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("Unable to parse {}", value))?;
+    if values.len() != 3 {
+        return Err(format!(
+            "Expected three comma-separated values, got {}",
+            value
+        ));
+    }
+    let mut triple = [T::default(); 3];
+    triple.copy_from_slice(&values);
+    Ok(triple)
+}
+
+fn is_triple<T: Copy + Default + FromStr>(value: String) -> Result<(), String> {
+    parse_triple::<T>(&value).map(|_| ())
+}
+
+fn is_expected_bank_hash(value: String) -> Result<(), String> {
+    parse_expected_bank_hash(&value)
+        .map(|_| ())
+        .map_err(|err| err.to_string())
+}
+
+// Prints the error and exits with the exit code of its kind
+fn exit_with_error(err: WinnerToolError) -> ! {
+    eprintln!("Error: {}", err);
+    exit(err.exit_code());
+}
+
+fn value_or_exit<T>(result: Result<T, WinnerToolError>) -> T {
+    result.unwrap_or_else(|err| exit_with_error(err))
+}
+
+fn ledger_path_or_exit(matches: &ArgMatches, config: &Config) -> PathBuf {
+    match matches.value_of("ledger") {
+        Some(ledger) => PathBuf::from(ledger),
+        None => config.ledger.clone().unwrap_or_else(|| {
+            exit_with_error(WinnerToolError::InvalidArgument(
+                "--ledger is not set on the command line or in --config".to_string(),
+            ))
+        }),
+    }
+}
+
+fn genesis_config_or_exit(ledger_path: &Path) -> GenesisConfig {
+    GenesisConfig::load(ledger_path).unwrap_or_else(|err| {
+        exit_with_error(WinnerToolError::Ledger(format!(
+            "Failed to open ledger genesis_config at {:?}: {}",
+            ledger_path, err
+        )))
+    })
+}
+
+fn blockstore_or_exit(ledger_path: &Path) -> Blockstore {
+    Blockstore::open(ledger_path).unwrap_or_else(|err| {
+        exit_with_error(WinnerToolError::Ledger(format!(
+            "Failed to open ledger at {:?}: {:?}",
+            ledger_path, err
+        )))
+    })
+}
+
+// First and final slot of the scored window, set on the command line or in the config file.
+// Epochs are converted to slots with the epoch schedule of the ledger.
+fn window_or_exit(
+    matches: &ArgMatches,
+    config: &Config,
+    genesis_config: &GenesisConfig,
+) -> (Option<Slot>, Option<Slot>) {
+    let epoch_schedule = &genesis_config.epoch_schedule;
+    let start_slot = if matches.is_present("start_slot") {
+        Some(value_t_or_exit!(matches, "start_slot", u64))
+    } else if matches.is_present("start_epoch") {
+        let start_epoch = value_t_or_exit!(matches, "start_epoch", u64);
+        Some(epoch_schedule.get_first_slot_in_epoch(start_epoch))
+    } else {
+        config.start_slot.or_else(|| {
+            config
+                .start_epoch
+                .map(|epoch| epoch_schedule.get_first_slot_in_epoch(epoch))
+        })
+    };
+    let final_slot = if matches.is_present("final_slot") {
+        Some(value_t_or_exit!(matches, "final_slot", u64))
+    } else if matches.is_present("end_epoch") {
+        let end_epoch = value_t_or_exit!(matches, "end_epoch", u64);
+        Some(epoch_schedule.get_last_slot_in_epoch(end_epoch))
+    } else {
+        config.final_slot.or_else(|| {
+            config
+                .end_epoch
+                .map(|epoch| epoch_schedule.get_last_slot_in_epoch(epoch))
+        })
+    };
+    if let (Some(start_slot), Some(final_slot)) = (start_slot, final_slot) {
+        if start_slot > final_slot {
+            exit_with_error(WinnerToolError::InvalidArgument(format!(
+                "The window starts at slot {} after the final slot {}",
+                start_slot, final_slot
+            )));
+        }
+    }
+    (start_slot, final_slot)
+}
+
+// Prints the warnings of the pre-flight checks and exits if any check failed
+fn preflight_or_exit(report: PreflightReport) {
+    for finding in &report.findings {
+        if finding.severity == Severity::Warning {
+            eprintln!("{}", finding);
+        }
+    }
+    value_or_exit(report.into_result());
+}
+
 fn main() {
     solana_logger::setup_with_filter("solana=info");
 
+    let default_checkpoint_interval = checkpoint::DEFAULT_CHECKPOINT_INTERVAL.to_string();
     let matches = App::new(crate_name!())
         .about(crate_description!())
         .version(crate_version!())
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .value_name("FILE")
+                .takes_value(true)
+                .help(
+                    "YAML file with the competition configuration; \
+                     command-line arguments override its values",
+                ),
+        )
         .arg(
             Arg::with_name("ledger")
                 .short("l")
                 .long("ledger")
                 .value_name("DIR")
                 .takes_value(true)
-                .required(true)
                 .help("Use directory for ledger location"),
         )
         .arg(
@@ -66,7 +211,6 @@ fn main() {
                 .long("baseline-validator")
                 .value_name("PUBKEY")
                 .takes_value(true)
-                .required(true)
                 .validator(is_pubkey_or_keypair)
                 .help("Public key of the baseline validator"),
         )
@@ -77,15 +221,45 @@ fn main() {
                 .multiple(true)
                 .takes_value(true)
                 .validator(is_pubkey)
-                .help("Exclude this public keys from the rewards calculation"),
+                .help(
+                    "Exclude this public keys from the rewards calculation, \
+                     in addition to those excluded by --config",
+                ),
+        )
+        .arg(
+            Arg::with_name("start_slot")
+                .long("start-slot")
+                .value_name("SLOT")
+                .takes_value(true)
+                .validator(is_parsable::<u64>)
+                .conflicts_with("start_epoch")
+                .help("Score the window of the ledger that starts at this slot"),
+        )
+        .arg(
+            Arg::with_name("start_epoch")
+                .long("start-epoch")
+                .value_name("EPOCH")
+                .takes_value(true)
+                .validator(is_parsable::<u64>)
+                .help("Score the window of the ledger that starts with this epoch"),
         )
         .arg(
             Arg::with_name("final_slot")
                 .long("final-slot")
                 .value_name("SLOT")
                 .takes_value(true)
+                .conflicts_with("end_epoch")
+                .validator(is_parsable::<u64>)
                 .help("Final slot of TdS ledger"),
         )
+        .arg(
+            Arg::with_name("end_epoch")
+                .long("end-epoch")
+                .value_name("EPOCH")
+                .takes_value(true)
+                .validator(is_parsable::<u64>)
+                .help("Score the window of the ledger that ends with this epoch"),
+        )
         .arg(
             Arg::with_name("pubkey_map_file")
                 .long("pubkey-map-file")
@@ -94,35 +268,360 @@ fn main() {
                 .takes_value(true)
                 .help("YAML file that maps validator identity pubkeys to keybase user id"),
         )
+        .arg(
+            Arg::with_name("missed_leader_slot_weight")
+                .long("missed-leader-slot-weight")
+                .value_name("VOTES")
+                .takes_value(true)
+                .validator(is_parsable::<u64>)
+                .help("Number of missed votes that a missed leader slot counts as [default: 10]"),
+        )
+        .arg(
+            Arg::with_name("availability_buckets")
+                .long("availability-buckets")
+                .value_name("HIGH,MEDIUM,LOW")
+                .takes_value(true)
+                .validator(is_triple::<f64>)
+                .help(
+                    "Fractions of the baseline availability that separate the buckets \
+                     [default: 0.95,0.75,0.5]",
+                ),
+        )
+        .arg(
+            Arg::with_name("max_vote_delay")
+                .long("max-vote-delay")
+                .value_name("SLOTS")
+                .takes_value(true)
+                .validator(is_parsable::<u64>)
+                .help(
+                    "Number of slots after which a vote is too late to count towards \
+                     the latency score [default: 10]",
+                ),
+        )
+        .arg(
+            Arg::with_name("latency_buckets")
+                .long("latency-buckets")
+                .value_name("HIGH,MEDIUM,LOW")
+                .takes_value(true)
+                .validator(is_triple::<f64>)
+                .help(
+                    "Fractions of the baseline latency score that separate the buckets \
+                     [default: 0.95,0.75,0.5]",
+                ),
+        )
+        .arg(
+            Arg::with_name("rewards_buckets")
+                .long("rewards-buckets")
+                .value_name("HIGH,MEDIUM,LOW")
+                .takes_value(true)
+                .validator(is_triple::<u64>)
+                .help(
+                    "Percentiles of the ranked validators that separate the rewards earned \
+                     buckets [default: 25,50,90]",
+                ),
+        )
+        .arg(
+            Arg::with_name("category")
+                .long("category")
+                .value_name("NAMES")
+                .takes_value(true)
+                .multiple(true)
+                .use_delimiter(true)
+                .help(
+                    "Comma-separated categories to score \
+                     [default: rewards_earned,availability,latency]",
+                ),
+        )
+        .arg(
+            Arg::with_name("checkpoint_dir")
+                .long("checkpoint-dir")
+                .value_name("DIR")
+                .takes_value(true)
+                .help("Periodically save checkpoints of the replay into this directory"),
+        )
+        .arg(
+            Arg::with_name("checkpoint_interval")
+                .long("checkpoint-interval")
+                .value_name("SLOTS")
+                .takes_value(true)
+                .default_value(&default_checkpoint_interval)
+                .validator(is_parsable::<u64>)
+                .help("Minimum number of slots between checkpoints"),
+        )
+        .arg(
+            Arg::with_name("resume_from")
+                .long("resume-from")
+                .value_name("DIR")
+                .takes_value(true)
+                .help(
+                    "Resume replay from this checkpoint, or from the latest checkpoint \
+                     in this directory",
+                ),
+        )
+        .arg(
+            Arg::with_name("snapshot")
+                .long("snapshot")
+                .value_name("ARCHIVE")
+                .takes_value(true)
+                .conflicts_with("resume_from")
+                .help(
+                    "Start replay from the bank in this snapshot archive instead of genesis. \
+                     Scoring starts no earlier than the snapshot slot",
+                ),
+        )
+        .arg(
+            Arg::with_name("metrics")
+                .long("metrics")
+                .value_name("HOST:PORT")
+                .takes_value(true)
+                .help("Serve replay progress and validator scores to Prometheus at this address"),
+        )
+        .arg(
+            Arg::with_name("metrics_linger")
+                .long("metrics-linger")
+                .value_name("SECONDS")
+                .takes_value(true)
+                .default_value("0")
+                .validator(is_parsable::<u64>)
+                .help("Time to keep serving the final metrics of a run before exiting"),
+        )
+        .arg(
+            Arg::with_name("replay_threads")
+                .long("replay-threads")
+                .value_name("N")
+                .takes_value(true)
+                .validator(is_parsable::<usize>)
+                .help("Number of threads that replay transactions [default: one per core]"),
+        )
+        .arg(
+            Arg::with_name("verify_poh")
+                .long("verify-poh")
+                .takes_value(false)
+                .help("Verify the proof of history of every replayed slot; slows down replay"),
+        )
+        .arg(
+            Arg::with_name("expected_bank_hash")
+                .long("expected-bank-hash")
+                .value_name("SLOT=HASH")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .validator(is_expected_bank_hash)
+                .help(
+                    "Fail unless the replayed bank at SLOT has this trusted hash; \
+                     may be specified multiple times",
+                ),
+        )
+        .arg(
+            Arg::with_name("skip_preflight")
+                .long("skip-preflight")
+                .takes_value(false)
+                .help("Replay the ledger even if the pre-flight checks fail"),
+        )
+        .arg(
+            Arg::with_name("output_format")
+                .long("output")
+                .value_name("FORMAT")
+                .takes_value(true)
+                .possible_values(&["text", "json", "csv", "markdown", "html"])
+                .default_value("text")
+                .help("Output format of the results"),
+        )
+        .subcommand(
+            SubCommand::with_name("merge-ledgers")
+                .about(
+                    "Merge the shreds of several ledgers into a new ledger, \
+                     reporting which slots came from which ledger",
+                )
+                .arg(
+                    Arg::with_name("source")
+                        .long("source")
+                        .value_name("DIR")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .required(true)
+                        .help(
+                            "Ledger to merge; may be specified multiple times, \
+                             earlier ledgers win conflicting versions of a slot. \
+                             The ledger of a running validator cannot be opened, \
+                             merge a copy of it instead",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("into")
+                        .long("into")
+                        .value_name("DIR")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Empty directory to write the merged ledger to"),
+                ),
+        )
+        .subcommand(SubCommand::with_name("audit").about(
+            "Report incomplete, orphan and dead slots and gaps in the ledger \
+             between the start and final slot",
+        ))
+        .subcommand(SubCommand::with_name("check").about(
+            "Check the baseline validator, exclusions, final slot and pubkey map \
+             against the ledger without replaying it",
+        ))
+        .subcommand(
+            SubCommand::with_name("record")
+                .about(
+                    "Replay the ledger and record the events that the categories are scored from",
+                )
+                .arg(
+                    Arg::with_name("event_log")
+                        .long("event-log")
+                        .value_name("FILE")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Write the event log to this file"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("score")
+                .about("Score the categories from a recorded event log instead of the ledger")
+                .arg(
+                    Arg::with_name("event_log")
+                        .long("event-log")
+                        .value_name("FILE")
+                        .takes_value(true)
+                        .required(true)
+                        .help("Event log written by the record subcommand"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("watch")
+                .about("Keep scoring the ledger as it grows, printing the results at every pass")
+                .arg(
+                    Arg::with_name("interval")
+                        .long("interval")
+                        .value_name("SLOTS")
+                        .takes_value(true)
+                        .default_value("1000")
+                        .validator(is_parsable::<u64>)
+                        .help(
+                            "Number of rooted slots between two scorings, \
+                             which also happen at the start of every epoch",
+                        ),
+                )
+                .arg(
+                    Arg::with_name("poll_interval")
+                        .long("poll-interval")
+                        .value_name("SECONDS")
+                        .takes_value(true)
+                        .default_value("10")
+                        .validator(is_parsable::<u64>)
+                        .help("Time to wait for new roots once the ledger has been scored"),
+                )
+                .arg(
+                    Arg::with_name("serve")
+                        .long("serve")
+                        .value_name("HOST:PORT")
+                        .takes_value(true)
+                        .help(
+                            "Serve the latest standings over HTTP at this address \
+                             instead of printing them",
+                        ),
+                ),
+        )
         .get_matches();
 
-    let ledger_path = PathBuf::from(value_t_or_exit!(matches, "ledger", String));
-    let starting_balance_sol = value_t_or_exit!(matches, "starting_balance", f64);
-    let baseline_validator = pubkey_of(&matches, "baseline_validator").unwrap();
-    let excluded_set: HashSet<Pubkey> = if matches.is_present("exclude_pubkey") {
+    // Merging ledgers needs none of the scoring options
+    if let ("merge-ledgers", Some(merge_matches)) = matches.subcommand() {
+        let sources: Vec<PathBuf> = merge_matches
+            .values_of("source")
+            .unwrap()
+            .map(PathBuf::from)
+            .collect();
+        let output = PathBuf::from(merge_matches.value_of("into").unwrap());
+        eprintln!("Merging ledgers...");
+        let report = value_or_exit(merge::merge_ledgers(&sources, &output));
+        if matches.value_of("output_format") == Some("json") {
+            println!("{}", report.to_json());
+        } else {
+            println!("{}", report.summary());
+        }
+        return;
+    }
+
+    let config = match matches.value_of("config") {
+        Some(config_file) => value_or_exit(Config::load(Path::new(config_file))),
+        None => Config::default(),
+    };
+
+    // Auditing a ledger needs only the ledger and the window
+    if matches.subcommand_name() == Some("audit") {
+        let ledger_path = ledger_path_or_exit(&matches, &config);
+        let genesis_config = genesis_config_or_exit(&ledger_path);
+        let (start_slot, final_slot) = window_or_exit(&matches, &config, &genesis_config);
+        let blockstore = blockstore_or_exit(&ledger_path);
+        eprintln!("Auditing ledger...");
+        let audit = value_or_exit(audit::audit_ledger(
+            &blockstore,
+            start_slot.unwrap_or(0),
+            final_slot,
+        ));
+        if matches.value_of("output_format") == Some("json") {
+            println!("{}", audit.to_json());
+        } else {
+            println!("{}", audit.summary());
+        }
+        value_or_exit(audit.into_result());
+        return;
+    }
+
+    let starting_balance_sol = match config.starting_balance {
+        Some(starting_balance) if matches.occurrences_of("starting_balance") == 0 => {
+            starting_balance
+        }
+        _ => value_t_or_exit!(matches, "starting_balance", f64),
+    };
+    // Only scoring requires a baseline validator, the pre-flight checks of a scoring run report a
+    // missing one
+    let baseline_validator = pubkey_of(&matches, "baseline_validator")
+        .or_else(|| value_or_exit(config.baseline_validator()));
+    let mut excluded_set: HashSet<Pubkey> = value_or_exit(config.excluded_set());
+    if matches.is_present("exclude_pubkey") {
         let exclude_pubkeys = values_t_or_exit!(matches, "exclude_pubkey", Pubkey);
-        exclude_pubkeys.into_iter().collect()
+        excluded_set.extend(exclude_pubkeys);
+    }
+    let output_format = value_t_or_exit!(matches, "output_format", String);
+    let category_names = if matches.is_present("category") {
+        values_t_or_exit!(matches, "category", String)
     } else {
-        HashSet::new()
+        config.categories.clone()
     };
-    let final_slot = value_t!(matches, "final_slot", u64).ok();
 
-    let pubkey_map_file = value_t_or_exit!(matches, "pubkey_map_file", String);
+    let mut parameters = config.parameters.clone();
+    if let Ok(weight) = value_t!(matches, "missed_leader_slot_weight", u64) {
+        parameters.availability.missed_leader_slot_weight = weight;
+    }
+    if let Some(buckets) = matches.value_of("availability_buckets") {
+        parameters.availability.baseline_buckets =
+            value_or_exit(parse_triple(buckets).map_err(WinnerToolError::InvalidArgument));
+    }
+    if let Ok(max_vote_delay) = value_t!(matches, "max_vote_delay", u64) {
+        parameters.latency.max_vote_delay = max_vote_delay;
+    }
+    if let Some(buckets) = matches.value_of("latency_buckets") {
+        parameters.latency.baseline_buckets =
+            value_or_exit(parse_triple(buckets).map_err(WinnerToolError::InvalidArgument));
+    }
+    if let Some(percentiles) = matches.value_of("rewards_buckets") {
+        parameters.rewards_earned.bucket_percentiles =
+            value_or_exit(parse_triple(percentiles).map_err(WinnerToolError::InvalidArgument));
+    }
+    value_or_exit(parameters.validate());
+
     let pubkey_map: HashMap<String, String> =
-        serde_yaml::from_reader(fs::File::open(&pubkey_map_file).unwrap_or_else(|err| {
-            eprintln!(
-                "Error: Unable to open --pubkey-map-file {}: {}",
-                pubkey_map_file, err
-            );
-            exit(1);
-        }))
-        .unwrap_or_else(|err| {
-            eprintln!(
-                "Error: Unable to parse --pubkey-map-file {}: {}",
-                pubkey_map_file, err
-            );
-            exit(1);
-        });
+        if matches.occurrences_of("pubkey_map_file") == 0 && !config.pubkey_map_files.is_empty() {
+            value_or_exit(config.pubkey_map())
+        } else {
+            let pubkey_map_file = value_t_or_exit!(matches, "pubkey_map_file", String);
+            value_or_exit(config::load_pubkey_map(Path::new(&pubkey_map_file)))
+        };
     let pubkey_to_keybase = |pubkey: &solana_sdk::pubkey::Pubkey| -> String {
         let pubkey = pubkey.to_string();
         match pubkey_map.get(&pubkey) {
@@ -131,46 +630,11 @@ fn main() {
         }
     };
 
-    let genesis_config = GenesisConfig::load(&ledger_path).unwrap_or_else(|err| {
-        eprintln!(
-            "Failed to open ledger genesis_config at {:?}: {}",
-            ledger_path, err
-        );
-        exit(1);
-    });
-
-    let blockstore = Blockstore::open(&ledger_path).unwrap_or_else(|err| {
-        eprintln!("Failed to open ledger at {:?}: {:?}", ledger_path, err);
-        exit(1);
-    });
-
-    // Track voter record after each entry
-    let voter_record: Arc<RwLock<VoterRecord>> = Arc::default();
-    let slot_voter_segments: Arc<RwLock<SlotVoterSegments>> = Arc::default();
-    let entry_callback = {
-        let voter_record = voter_record.clone();
-        let slot_voter_segments = slot_voter_segments.clone();
-        Arc::new(move |bank: &Bank| {
-            confirmation_latency::on_entry(
-                bank.slot(),
-                bank.vote_accounts(),
-                &mut voter_record.write().unwrap(),
-                &mut slot_voter_segments.write().unwrap(),
-            );
-        })
-    };
-
-    let opts = ProcessOptions {
-        poh_verify: false,
-        dev_halt_at_slot: final_slot,
-        full_leader_cache: true,
-        entry_callback: Some(entry_callback),
-        override_num_threads: Some(1),
-        ..ProcessOptions::default()
-    };
-
-    let print_winners = |winners: winner::Winners| {
-        println!("\n{:?}:", winners.category);
+    let print_winners = |winners: &winner::Winners| {
+        println!("\n{}:", winners.category);
+        if let Some(baseline) = winners.describe_baseline() {
+            println!("  {}", baseline);
+        }
         if !winners.top_winners.is_empty() {
             println!("  Top Three:");
             for (index, winner) in winners.top_winners.iter().enumerate() {
@@ -178,76 +642,257 @@ fn main() {
                     "    {}. {:<44}: {}",
                     index + 1,
                     pubkey_to_keybase(&winner.0),
-                    winner.1
+                    winners.category.describe_score(&winner.1)
                 );
             }
         }
-        for (bucket_name, winners) in winners.bucket_winners.iter() {
+        for (bucket_name, bucket) in winners.bucket_winners.iter() {
             println!("  {}:", bucket_name);
-            if winners.is_empty() {
+            if bucket.is_empty() {
                 println!("    None");
             } else {
-                for winner in winners {
-                    println!("    - {:<44}: {}", pubkey_to_keybase(&winner.0), winner.1);
+                for winner in bucket {
+                    println!(
+                        "    - {:<44}: {}",
+                        pubkey_to_keybase(&winner.0),
+                        winners.category.describe_score(&winner.1)
+                    );
                 }
             }
         }
+        if !winners.epochs.is_empty() {
+            println!("  By Epoch:");
+            for (pubkey, ranks) in winners.epoch_table() {
+                let cells: Vec<_> = winners
+                    .epochs
+                    .iter()
+                    .zip(ranks)
+                    .map(|(standings, rank)| match rank {
+                        Some((rank, score)) => {
+                            format!("epoch {}: #{} {}", standings.epoch, rank, score)
+                        }
+                        None => format!("epoch {}: -", standings.epoch),
+                    })
+                    .collect();
+                println!(
+                    "    - {:<44}: {}",
+                    pubkey_to_keybase(&pubkey),
+                    cells.join(", ")
+                );
+            }
+        }
+    };
+
+    let print_stakes = |stakes: &[(Pubkey, u64)]| {
+        let mut total_stake = 0;
+        let mut stake_lines = vec![];
+        for (node_pubkey, stake) in stakes {
+            stake_lines.push(format!(
+                "* {:<44}: {:.5} SOL ({} lamports) in stake",
+                pubkey_to_keybase(node_pubkey),
+                lamports_to_sol(*stake),
+                stake,
+            ));
+            total_stake += stake;
+        }
+        stake_lines.sort();
+        println!(
+            "\nTotal stake: {:.5} SOL ({} lamports)\n{}",
+            lamports_to_sol(total_stake),
+            total_stake,
+            stake_lines.join("\n"),
+        );
+    };
+
+    // Servers share the standings of a watched ledger and the metrics of the run
+    let leaderboard: SharedLeaderboard = Arc::new(RwLock::new(None));
+    let metrics = Arc::new(Metrics::default());
+    let start_server = |addr: &str| {
+        let listener = TcpListener::bind(addr).unwrap_or_else(|err| {
+            exit_with_error(WinnerToolError::Server(format!(
+                "Unable to listen on {}: {}",
+                addr, err
+            )))
+        });
+        server::serve(listener, leaderboard.clone(), metrics.clone());
+    };
+    if let Some(addr) = matches.value_of("metrics") {
+        start_server(addr);
+        eprintln!("Serving metrics at http://{}/metrics", addr);
+    }
+
+    let mut options = ScoringOptions {
+        baseline_validator,
+        excluded_set,
+        starting_balance: sol_to_lamports(starting_balance_sol),
+        start_slot: None,
+        final_slot: None,
+        parameters,
+        checkpoint_path: matches.value_of("checkpoint_dir").map(PathBuf::from),
+        checkpoint_interval: value_t_or_exit!(matches, "checkpoint_interval", u64),
+        resume_from: matches.value_of("resume_from").map(PathBuf::from),
+        snapshot_archive: matches.value_of("snapshot").map(PathBuf::from),
+        replay_threads: value_t!(matches, "replay_threads", usize).ok(),
+        verify_poh: matches.is_present("verify_poh"),
+        expected_bank_hashes: matches
+            .values_of("expected_bank_hash")
+            .into_iter()
+            .flatten()
+            .map(|value| value_or_exit(parse_expected_bank_hash(value)))
+            .collect(),
+        metrics: Some(metrics.clone()),
+    };
+
+    let registry = CategoryRegistry::default();
+    let create_categories = |options: &ScoringOptions| {
+        if category_names.is_empty() {
+            registry.create_all(options)
+        } else {
+            value_or_exit(registry.create(&category_names, options))
+        }
+    };
+
+    let render_results = |results: &ScoringResults| match output_format.as_str() {
+        "json" => {
+            let report = report::Report::new(results, &pubkey_map);
+            println!("{}", report.to_json());
+        }
+        "csv" => println!("{}", report::render_csv(results, &pubkey_map)),
+        "markdown" => println!("{}", page::render_markdown(results, &pubkey_map)),
+        "html" => println!("{}", page::render_html(results, &pubkey_map)),
+        _ => {
+            let ScoringResults {
+                first_slot,
+                last_slot,
+                stakes,
+                winners,
+                parameters,
+                ..
+            } = results;
+            println!("Slots {} to {}", first_slot, last_slot);
+            print_stakes(stakes);
+            for winners in winners {
+                print_winners(winners);
+            }
+            println!("\nParameters:\n{}", parameters.to_yaml());
+        }
     };
 
-    println!("Processing ledger...");
-    match process_blockstore(&genesis_config, &blockstore, vec![], opts) {
-        Ok((bank_forks, _bank_forks_info, leader_schedule_cache)) => {
-            let bank = bank_forks.working_bank();
-
-            let mut total_stake = 0;
-            let mut stakes = vec![];
-            for (stake, vote_account) in bank.vote_accounts().values() {
-                let vote_state = solana_vote_program::vote_state::VoteState::from(&vote_account)
-                    .unwrap_or_default();
-                if !excluded_set.contains(&vote_state.node_pubkey) {
-                    stakes.push(format!(
-                        "* {:<44}: {:.5} SOL ({} lamports) in stake",
-                        pubkey_to_keybase(&vote_state.node_pubkey),
-                        lamports_to_sol(*stake),
-                        stake,
+    let results = match matches.subcommand() {
+        ("score", Some(score_matches)) => {
+            let event_log = PathBuf::from(score_matches.value_of("event_log").unwrap());
+            eprintln!("Scoring event log...");
+            let categories = create_categories(&options);
+            score_event_log(&event_log, &options, categories)
+        }
+        (subcommand, subcommand_matches) => {
+            let ledger_path = ledger_path_or_exit(&matches, &config);
+            let genesis_config = genesis_config_or_exit(&ledger_path);
+            let (start_slot, final_slot) = window_or_exit(&matches, &config, &genesis_config);
+            options.start_slot = start_slot;
+            options.final_slot = final_slot;
+            let skip_preflight = matches.is_present("skip_preflight");
+            let run_preflight = |blockstore: &Blockstore, options: &ScoringOptions| {
+                let scoring = subcommand != "record";
+                preflight::check(&genesis_config, blockstore, options, &pubkey_map, scoring)
+            };
+
+            if let ("watch", Some(watch_matches)) = (subcommand, subcommand_matches) {
+                let watch_options = WatchOptions {
+                    interval: value_t_or_exit!(watch_matches, "interval", u64),
+                    poll_interval: Duration::from_secs(value_t_or_exit!(
+                        watch_matches,
+                        "poll_interval",
+                        u64
+                    )),
+                };
+                let serve_standings = match watch_matches.value_of("serve") {
+                    Some(addr) => {
+                        start_server(addr);
+                        eprintln!("Serving standings at http://{}/", addr);
+                        true
+                    }
+                    None => false,
+                };
+
+                if !skip_preflight {
+                    let ledger = value_or_exit(WatchedLedger::open(
+                        &ledger_path,
+                        watch_options.poll_interval,
                     ));
-                    total_stake += stake;
+                    preflight_or_exit(run_preflight(&ledger.blockstore, &options));
                 }
+
+                eprintln!("Watching ledger...");
+                let categories = create_categories(&options);
+                value_or_exit(watch_ledger(
+                    &genesis_config,
+                    &ledger_path,
+                    options,
+                    categories,
+                    &watch_options,
+                    |results| {
+                        metrics.update_scores(&results, &pubkey_map);
+                        if serve_standings {
+                            *leaderboard.write().unwrap() =
+                                Some(Leaderboard::new(&results, &pubkey_map));
+                            eprintln!(
+                                "Standings updated for slots {} to {}",
+                                results.first_slot, results.last_slot
+                            );
+                        } else {
+                            render_results(&results);
+                        }
+                    },
+                ));
+                return;
+            }
+
+            let blockstore = blockstore_or_exit(&ledger_path);
+
+            if subcommand == "check" {
+                preflight_or_exit(run_preflight(&blockstore, &options));
+                println!("Pre-flight checks passed");
+                return;
+            }
+            if !skip_preflight {
+                preflight_or_exit(run_preflight(&blockstore, &options));
+            }
+
+            if let ("record", Some(record_matches)) = (subcommand, subcommand_matches) {
+                let event_log = PathBuf::from(record_matches.value_of("event_log").unwrap());
+                eprintln!("Recording ledger events...");
+                let session =
+                    ScoringSession::with_categories(&genesis_config, &blockstore, options, vec![]);
+                value_or_exit(session.record(&event_log));
+                return;
+            }
+
+            eprintln!("Processing ledger...");
+            let categories = create_categories(&options);
+            let session =
+                ScoringSession::with_categories(&genesis_config, &blockstore, options, categories);
+            session.run()
+        }
+    };
+
+    match results {
+        Ok(mut results) => {
+            metrics.update_scores(&results, &pubkey_map);
+            let render_start = Instant::now();
+            render_results(&results);
+            results
+                .timings
+                .record("render report", render_start.elapsed());
+            eprintln!("\nTime spent:\n{}", results.timings.summary());
+
+            // Give Prometheus the chance to scrape the scores before the server goes away
+            let linger = value_t_or_exit!(matches, "metrics_linger", u64);
+            if matches.is_present("metrics") && linger > 0 {
+                eprintln!("Serving the final metrics for {}s", linger);
+                thread::sleep(Duration::from_secs(linger));
             }
-            stakes.sort();
-            println!(
-                "\nTotal stake: {:.5} SOL ({} lamports)\n{}",
-                lamports_to_sol(total_stake),
-                total_stake,
-                stakes.join("\n"),
-            );
-
-            let starting_balance = sol_to_lamports(starting_balance_sol);
-            let rewards_earned_winners =
-                rewards_earned::compute_winners(&bank, &excluded_set, starting_balance);
-            print_winners(rewards_earned_winners);
-
-            let availability_winners = availability::compute_winners(
-                &bank,
-                &blockstore,
-                &baseline_validator,
-                &excluded_set,
-                &leader_schedule_cache,
-            );
-            print_winners(availability_winners);
-
-            let latency_winners = confirmation_latency::compute_winners(
-                &bank,
-                &baseline_validator,
-                &excluded_set,
-                &mut voter_record.write().unwrap(),
-                &mut slot_voter_segments.write().unwrap(),
-            );
-            print_winners(latency_winners);
-        }
-        Err(err) => {
-            eprintln!("Failed to process ledger: {:?}", err);
-            exit(1);
         }
+        Err(err) => exit_with_error(err),
     }
 }
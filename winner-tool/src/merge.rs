@@ -0,0 +1,411 @@
+//! Merges the blockstores of several nodes into a new ledger, so that the shreds one node missed
+//! can be filled in from another. Sources are ranked in the order given: the first source that
+//! holds a slot decides its parent and the content of every shred, and later sources only fill
+//! in the shreds it is missing. A source whose version of a slot names another parent or holds a
+//! different shred at the same index is a conflicting version, which is reported and left out.
+//!
+//! Only data shreds are merged, since they hold every entry once a slot is complete.
+
+use crate::error::WinnerToolError;
+use serde::Serialize;
+use solana_ledger::{
+    blockstore::{Blockstore, SlotMeta},
+    shred::Shred,
+};
+use solana_sdk::{clock::Slot, genesis_config::GenesisConfig};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Shreds of a slot in one source
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SlotVersion {
+    pub parent_slot: Slot,
+    /// Payload of every data shred the source holds, by shred index
+    pub shreds: BTreeMap<u64, Vec<u8>>,
+}
+
+/// Version of a slot in a source that disagrees with the version merged from earlier sources
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Conflict {
+    pub slot: Slot,
+    /// Index of the conflicting source
+    pub source: usize,
+    pub reason: String,
+}
+
+/// Sources that a merged slot was built from
+#[derive(Debug, PartialEq, Serialize)]
+pub struct MergedSlot {
+    pub slot: Slot,
+    /// Index of the source that decided the parent and content of the slot
+    pub primary: usize,
+    /// Indexes of the later sources that filled in missing shreds
+    pub filled_from: Vec<usize>,
+    /// Whether every data shred of the slot is in the merged ledger
+    pub is_full: bool,
+}
+
+/// Outcome of a merge
+#[derive(Debug, PartialEq, Serialize)]
+pub struct MergeReport {
+    pub sources: Vec<PathBuf>,
+    pub output: PathBuf,
+    pub slots: Vec<MergedSlot>,
+    pub roots: usize,
+    pub conflicts: Vec<Conflict>,
+}
+
+// Formats sorted slots as ranges of consecutive slots, e.g. "3-5, 8"
+fn slot_ranges(slots: &[Slot]) -> String {
+    let mut ranges: Vec<(Slot, Slot)> = vec![];
+    for slot in slots {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == *slot => *end = *slot,
+            _ => ranges.push((*slot, *slot)),
+        }
+    }
+    ranges
+        .iter()
+        .map(|(start, end)| {
+            if start == end {
+                start.to_string()
+            } else {
+                format!("{}-{}", start, end)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl MergeReport {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    /// Renders the report as text, with the slots each source provided
+    pub fn summary(&self) -> String {
+        let mut lines = vec![format!(
+            "Merged {} ledgers into {}: {} slots, {} roots",
+            self.sources.len(),
+            self.output.display(),
+            self.slots.len(),
+            self.roots
+        )];
+        for (index, source) in self.sources.iter().enumerate() {
+            let primary: Vec<Slot> = self
+                .slots
+                .iter()
+                .filter(|merged| merged.primary == index)
+                .map(|merged| merged.slot)
+                .collect();
+            let filled: Vec<Slot> = self
+                .slots
+                .iter()
+                .filter(|merged| merged.filled_from.contains(&index))
+                .map(|merged| merged.slot)
+                .collect();
+            lines.push(format!("Source {}: {}", index, source.display()));
+            lines.push(format!(
+                "  Slots taken from it ({}): {}",
+                primary.len(),
+                slot_ranges(&primary)
+            ));
+            lines.push(format!(
+                "  Slots filled in from it ({}): {}",
+                filled.len(),
+                slot_ranges(&filled)
+            ));
+        }
+        let incomplete: Vec<Slot> = self
+            .slots
+            .iter()
+            .filter(|merged| !merged.is_full)
+            .map(|merged| merged.slot)
+            .collect();
+        lines.push(format!(
+            "Incomplete slots ({}): {}",
+            incomplete.len(),
+            slot_ranges(&incomplete)
+        ));
+        lines.push(format!("Conflicting versions: {}", self.conflicts.len()));
+        for conflict in &self.conflicts {
+            lines.push(format!(
+                "  - slot {} in source {}: {}",
+                conflict.slot, conflict.source, conflict.reason
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+// Merges the versions of `slot` in every source, ordered by source index. Returns the merged
+// shreds, the sources they came from and the conflicting versions.
+fn merge_slot(
+    slot: Slot,
+    versions: Vec<(usize, SlotVersion)>,
+) -> Option<(SlotVersion, MergedSlot, Vec<Conflict>)> {
+    let mut versions = versions
+        .into_iter()
+        .filter(|(_, version)| !version.shreds.is_empty());
+    let (primary, mut merged) = versions.next()?;
+    let mut filled_from = vec![];
+    let mut conflicts = vec![];
+    for (source, version) in versions {
+        if version.parent_slot != merged.parent_slot {
+            conflicts.push(Conflict {
+                slot,
+                source,
+                reason: format!(
+                    "parent slot {} instead of {}",
+                    version.parent_slot, merged.parent_slot
+                ),
+            });
+            continue;
+        }
+        let differing = version
+            .shreds
+            .iter()
+            .find(|(index, payload)| {
+                merged
+                    .shreds
+                    .get(index)
+                    .map(|merged_payload| merged_payload != *payload)
+                    .unwrap_or(false)
+            })
+            .map(|(index, _)| *index);
+        if let Some(index) = differing {
+            conflicts.push(Conflict {
+                slot,
+                source,
+                reason: format!("different data shred at index {}", index),
+            });
+            continue;
+        }
+        let missing: Vec<(u64, Vec<u8>)> = version
+            .shreds
+            .into_iter()
+            .filter(|(index, _)| !merged.shreds.contains_key(index))
+            .collect();
+        if !missing.is_empty() {
+            filled_from.push(source);
+            merged.shreds.extend(missing);
+        }
+    }
+    let merged_slot = MergedSlot {
+        slot,
+        primary,
+        filled_from,
+        is_full: false,
+    };
+    Some((merged, merged_slot, conflicts))
+}
+
+fn ledger_error(path: &Path, action: &str, err: impl std::fmt::Debug) -> WinnerToolError {
+    WinnerToolError::Ledger(format!(
+        "Unable to {} ledger {}: {:?}",
+        action,
+        path.display(),
+        err
+    ))
+}
+
+// Version of `slot` in `blockstore`, made of every data shred it holds, read in one pass over
+// the shreds of the slot
+fn slot_version(
+    blockstore: &Blockstore,
+    path: &Path,
+    meta: &SlotMeta,
+) -> Result<SlotVersion, WinnerToolError> {
+    let shreds = blockstore
+        .slot_data_iterator(meta.slot)
+        .map_err(|err| ledger_error(path, "read", err))?
+        .map(|((_, index), payload)| (index, payload.into_vec()))
+        .collect();
+    Ok(SlotVersion {
+        parent_slot: meta.parent_slot,
+        shreds,
+    })
+}
+
+/// Merges the data shreds of the ledgers in `sources` into a new ledger at `output`, which must
+/// not exist yet or be empty. Every source must share the genesis config. The blockstore of this
+/// release of Solana can only be opened by one process at a time, so no source may be the ledger
+/// of a running validator.
+pub fn merge_ledgers(sources: &[PathBuf], output: &Path) -> Result<MergeReport, WinnerToolError> {
+    if sources.is_empty() {
+        return Err(WinnerToolError::InvalidArgument(
+            "No ledgers to merge".to_string(),
+        ));
+    }
+    let output_is_empty = fs::read_dir(output)
+        .map(|mut entries| entries.next().is_none())
+        .unwrap_or(true);
+    if !output_is_empty {
+        return Err(WinnerToolError::InvalidArgument(format!(
+            "{} is not empty; merge into a new directory",
+            output.display()
+        )));
+    }
+
+    let genesis_config = GenesisConfig::load(&sources[0])
+        .map_err(|err| ledger_error(&sources[0], "load the genesis config of", err))?;
+    for source in &sources[1..] {
+        let source_genesis_config = GenesisConfig::load(source)
+            .map_err(|err| ledger_error(source, "load the genesis config of", err))?;
+        if source_genesis_config.hash() != genesis_config.hash() {
+            return Err(WinnerToolError::InvalidArgument(format!(
+                "Ledger {} has a different genesis config than {}",
+                source.display(),
+                sources[0].display()
+            )));
+        }
+    }
+    let blockstores = sources
+        .iter()
+        .map(|source| {
+            Blockstore::open(source).map_err(|err| {
+                WinnerToolError::Ledger(format!(
+                    "Unable to open ledger {}: {:?}. The ledger of a running validator cannot be \
+                     merged, stop the validator or merge a copy of its ledger",
+                    source.display(),
+                    err
+                ))
+            })
+        })
+        .collect::<Result<Vec<_>, WinnerToolError>>()?;
+
+    fs::create_dir_all(output).map_err(|err| ledger_error(output, "create", err))?;
+    genesis_config
+        .write(output)
+        .map_err(|err| ledger_error(output, "write the genesis config of", err))?;
+    let merged_blockstore =
+        Blockstore::open(output).map_err(|err| ledger_error(output, "create", err))?;
+
+    let mut slots = BTreeSet::new();
+    let mut roots = BTreeSet::new();
+    for (blockstore, source) in blockstores.iter().zip(sources) {
+        let slot_metas = blockstore
+            .slot_meta_iterator(0)
+            .map_err(|err| ledger_error(source, "read", err))?;
+        for (slot, _) in slot_metas {
+            slots.insert(slot);
+            if blockstore.is_root(slot) {
+                roots.insert(slot);
+            }
+        }
+    }
+
+    let mut report = MergeReport {
+        sources: sources.to_vec(),
+        output: output.to_path_buf(),
+        slots: vec![],
+        roots: 0,
+        conflicts: vec![],
+    };
+    for slot in slots {
+        let mut versions = vec![];
+        for (index, (blockstore, source)) in blockstores.iter().zip(sources).enumerate() {
+            let meta = blockstore
+                .meta(slot)
+                .map_err(|err| ledger_error(source, "read", err))?;
+            if let Some(meta) = meta {
+                versions.push((index, slot_version(blockstore, source, &meta)?));
+            }
+        }
+        let (merged, mut merged_slot, conflicts) = match merge_slot(slot, versions) {
+            Some(merged) => merged,
+            None => continue,
+        };
+        let shreds = merged
+            .shreds
+            .into_iter()
+            .map(|(_, payload)| Shred::new_from_serialized_shred(payload))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| ledger_error(output, "decode a shred for", err))?;
+        merged_blockstore
+            .insert_shreds(shreds, None, true)
+            .map_err(|err| ledger_error(output, "write", err))?;
+        merged_slot.is_full = merged_blockstore
+            .meta(slot)
+            .map_err(|err| ledger_error(output, "read", err))?
+            .map(|meta| meta.is_full())
+            .unwrap_or(false);
+        report.slots.push(merged_slot);
+        report.conflicts.extend(conflicts);
+    }
+
+    // Roots of slots that could not be merged are left out
+    let roots: Vec<Slot> = roots
+        .into_iter()
+        .filter(|root| {
+            report
+                .slots
+                .binary_search_by_key(root, |merged| merged.slot)
+                .is_ok()
+        })
+        .collect();
+    merged_blockstore
+        .set_roots(&roots)
+        .map_err(|err| ledger_error(output, "write the roots of", err))?;
+    report.roots = roots.len();
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(parent_slot: Slot, shreds: &[(u64, u8)]) -> SlotVersion {
+        SlotVersion {
+            parent_slot,
+            shreds: shreds
+                .iter()
+                .map(|(index, byte)| (*index, vec![*byte]))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_merge_slot() {
+        assert!(merge_slot(5, vec![(0, version(4, &[]))]).is_none());
+
+        let (merged, merged_slot, conflicts) = merge_slot(
+            5,
+            vec![
+                (0, version(4, &[])),
+                (1, version(4, &[(0, 1), (2, 3)])),
+                (2, version(3, &[(1, 2)])),
+                (3, version(4, &[(0, 9), (3, 4)])),
+                (4, version(4, &[(0, 1), (1, 2), (3, 4)])),
+            ],
+        )
+        .unwrap();
+        assert_eq!(merged, version(4, &[(0, 1), (1, 2), (2, 3), (3, 4)]));
+        assert_eq!(merged_slot.primary, 1);
+        assert_eq!(merged_slot.filled_from, vec![4]);
+        assert_eq!(
+            conflicts,
+            vec![
+                Conflict {
+                    slot: 5,
+                    source: 2,
+                    reason: "parent slot 3 instead of 4".to_string(),
+                },
+                Conflict {
+                    slot: 5,
+                    source: 3,
+                    reason: "different data shred at index 0".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_slot_ranges() {
+        assert_eq!(slot_ranges(&[]), "");
+        assert_eq!(slot_ranges(&[3, 4, 5, 8, 10, 11]), "3-5, 8, 10-11");
+    }
+}
@@ -0,0 +1,303 @@
+//! Gauges of the replay progress and of the latest scores of every validator, rendered in the
+//! Prometheus text exposition format so that they can be scraped next to the cluster metrics.
+
+use crate::session::ScoringResults;
+use solana_sdk::{clock::Slot, pubkey::Pubkey};
+use std::cmp::max;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+/// Content type of the rendered metrics
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+// Time over which the rate of entries is measured
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Metrics of a run, updated by replay and by every scoring
+pub struct Metrics {
+    start: Instant,
+    replay_slot: AtomicU64,
+    entry_batches: AtomicU64,
+    // Tick height of the first bank replay processed entries in and the highest tick height
+    // reached since, whose difference is the number of tick entries replayed. Replaying a fork
+    // below the highest tick height does not lower the count.
+    tick_heights: Mutex<Option<(u64, u64)>>,
+    // Number of entry batches and ticks replayed at each rendering within the rate window, oldest
+    // first
+    entry_samples: Mutex<VecDeque<(Instant, u64)>>,
+    // Validator gauges of the latest scoring, rendered when it was scored
+    validator_metrics: RwLock<String>,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            start: Instant::now(),
+            replay_slot: AtomicU64::new(0),
+            entry_batches: AtomicU64::new(0),
+            tick_heights: Mutex::new(None),
+            entry_samples: Mutex::new(VecDeque::new()),
+            validator_metrics: RwLock::new(String::new()),
+        }
+    }
+}
+
+impl Metrics {
+    /// Observes a batch of transaction entries that replay processed in `slot`, whose bank has
+    /// reached `tick_height`. Replay reports neither the entries of a batch nor tick entries, so
+    /// batches are counted as such and ticks are counted from the tick height.
+    pub fn observe_entry_batch(&self, slot: Slot, tick_height: u64) {
+        self.replay_slot.store(slot, Ordering::Relaxed);
+        self.entry_batches.fetch_add(1, Ordering::Relaxed);
+        let mut tick_heights = self.tick_heights.lock().unwrap();
+        let (_, highest) = tick_heights.get_or_insert((tick_height, tick_height));
+        *highest = max(*highest, tick_height);
+    }
+
+    // Number of tick entries replayed so far
+    fn ticks(&self) -> u64 {
+        self.tick_heights
+            .lock()
+            .unwrap()
+            .map(|(first, highest)| highest - first)
+            .unwrap_or(0)
+    }
+
+    // Entry batches and ticks replayed per second since the latest rendering that is at least the
+    // rate window old, or since the oldest rendering or the start of the run when there is none
+    fn entry_rate(&self, now: Instant, entries: u64) -> f64 {
+        let mut entry_samples = self.entry_samples.lock().unwrap();
+        while entry_samples.len() > 1 && now.duration_since(entry_samples[1].0) >= RATE_WINDOW {
+            entry_samples.pop_front();
+        }
+        let (since, since_entries) = entry_samples.front().cloned().unwrap_or((self.start, 0));
+        entry_samples.push_back((now, entries));
+        let elapsed_secs = now.duration_since(since).as_secs_f64().max(1.);
+        entries.saturating_sub(since_entries) as f64 / elapsed_secs
+    }
+
+    /// Replaces the validator gauges with the results of the latest scoring
+    pub fn update_scores(&self, results: &ScoringResults, pubkey_map: &HashMap<String, String>) {
+        *self.validator_metrics.write().unwrap() = validator_metrics(results, pubkey_map);
+    }
+
+    /// Renders every metric in the Prometheus text format
+    pub fn render(&self) -> String {
+        let entry_batches = self.entry_batches.load(Ordering::Relaxed);
+        let ticks = self.ticks();
+        let entry_rate = self.entry_rate(Instant::now(), entry_batches + ticks);
+        let mut metrics = String::new();
+        write_family(
+            &mut metrics,
+            "tds_replay_slot",
+            "gauge",
+            "Slot that replay last processed entries in",
+            &[(
+                String::new(),
+                self.replay_slot.load(Ordering::Relaxed) as f64,
+            )],
+        );
+        write_family(
+            &mut metrics,
+            "tds_replay_entry_batches_total",
+            "counter",
+            "Batches of transaction entries processed by replay",
+            &[(String::new(), entry_batches as f64)],
+        );
+        write_family(
+            &mut metrics,
+            "tds_replay_ticks_total",
+            "counter",
+            "Tick entries processed by replay up to the highest tick height it reached",
+            &[(String::new(), ticks as f64)],
+        );
+        write_family(
+            &mut metrics,
+            "tds_replay_entries_per_second",
+            "gauge",
+            "Batches of transaction entries and tick entries processed by replay per second over \
+             the last minute",
+            &[(String::new(), entry_rate)],
+        );
+        metrics.push_str(&self.validator_metrics.read().unwrap());
+        metrics
+    }
+}
+
+// Escapes a label value of the text format
+fn label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn write_family(
+    metrics: &mut String,
+    name: &str,
+    kind: &str,
+    help: &str,
+    samples: &[(String, f64)],
+) {
+    writeln!(metrics, "# HELP {} {}", name, help).unwrap();
+    writeln!(metrics, "# TYPE {} {}", name, kind).unwrap();
+    for (labels, value) in samples {
+        if labels.is_empty() {
+            writeln!(metrics, "{} {}", name, value).unwrap();
+        } else {
+            writeln!(metrics, "{}{{{}}} {}", name, labels, value).unwrap();
+        }
+    }
+}
+
+// Gauges of the stake, the score in every category and the measurements the scores were derived
+// from, labeled with the identity and keybase username of each validator
+fn validator_metrics(results: &ScoringResults, pubkey_map: &HashMap<String, String>) -> String {
+    let labels = |pubkey: &Pubkey, category: Option<&str>| {
+        let mut labels = vec![];
+        if let Some(category) = category {
+            labels.push(format!("category=\"{}\"", label_value(category)));
+        }
+        labels.push(format!("identity=\"{}\"", pubkey));
+        if let Some(keybase) = pubkey_map.get(&pubkey.to_string()) {
+            labels.push(format!("keybase=\"{}\"", label_value(keybase)));
+        }
+        labels.join(",")
+    };
+
+    let stakes: Vec<_> = results
+        .stakes
+        .iter()
+        .map(|(pubkey, stake)| (labels(pubkey, None), *stake as f64))
+        .collect();
+    let mut scores = vec![];
+    let mut details: BTreeMap<&str, Vec<(String, f64)>> = BTreeMap::new();
+    for winners in &results.winners {
+        let category = winners.category.name;
+        for (pubkey, score) in &winners.results {
+            scores.push((labels(pubkey, Some(category)), score.value()));
+        }
+        for (pubkey, validator_details) in &winners.details {
+            for (name, value) in validator_details {
+                details
+                    .entry(*name)
+                    .or_default()
+                    .push((labels(pubkey, Some(category)), *value as f64));
+            }
+        }
+    }
+
+    let mut metrics = String::new();
+    write_family(
+        &mut metrics,
+        "tds_validator_stake_lamports",
+        "gauge",
+        "Active stake of the validator",
+        &stakes,
+    );
+    write_family(
+        &mut metrics,
+        "tds_validator_score",
+        "gauge",
+        "Score of the validator in the category",
+        &scores,
+    );
+    for (name, samples) in details {
+        write_family(
+            &mut metrics,
+            &format!("tds_validator_{}", name),
+            "gauge",
+            &format!(
+                "Raw {} measurement the score of the category was derived from",
+                name
+            ),
+            &samples,
+        );
+    }
+    metrics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::availability;
+    use crate::progress::PhaseTimings;
+    use crate::session::ScoringParameters;
+    use crate::winner::{Score, Winners};
+
+    #[test]
+    fn test_label_value() {
+        assert_eq!(label_value("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+
+    #[test]
+    fn test_render() {
+        let validator = Pubkey::new_rand();
+        let mut details = HashMap::new();
+        details.insert(validator, vec![("credits", 90), ("missed_leader_slots", 2)]);
+        let results = ScoringResults {
+            first_slot: 0,
+            last_slot: 100,
+            stakes: vec![(validator, 42)],
+            winners: vec![Winners {
+                category: availability::CATEGORY,
+                baseline: None,
+                results: vec![(validator, Score::Ratio(0.5))],
+                top_winners: vec![],
+                bucket_winners: vec![],
+                details,
+                epochs: vec![],
+            }],
+            parameters: ScoringParameters::default(),
+            timings: PhaseTimings::default(),
+        };
+        let mut pubkey_map = HashMap::new();
+        pubkey_map.insert(validator.to_string(), "alice".to_string());
+
+        let metrics = Metrics::default();
+        metrics.observe_entry_batch(7, 64);
+        metrics.observe_entry_batch(8, 70);
+        // A sibling fork at a lower tick height does not take ticks off the count
+        metrics.observe_entry_batch(9, 66);
+        metrics.update_scores(&results, &pubkey_map);
+        let rendered = metrics.render();
+
+        let labels = format!("identity=\"{}\",keybase=\"alice\"", validator);
+        for line in &[
+            "# TYPE tds_replay_slot gauge".to_string(),
+            "tds_replay_slot 9".to_string(),
+            "tds_replay_entry_batches_total 3".to_string(),
+            "tds_replay_ticks_total 6".to_string(),
+            format!("tds_validator_stake_lamports{{{}}} 42", labels),
+            format!(
+                "tds_validator_score{{category=\"availability\",{}}} 0.5",
+                labels
+            ),
+            format!(
+                "tds_validator_credits{{category=\"availability\",{}}} 90",
+                labels
+            ),
+            format!(
+                "tds_validator_missed_leader_slots{{category=\"availability\",{}}} 2",
+                labels
+            ),
+        ] {
+            assert!(rendered.lines().any(|rendered_line| rendered_line == line));
+        }
+    }
+
+    #[test]
+    fn test_entry_rate() {
+        let metrics = Metrics::default();
+        let start = metrics.start;
+        let at = |secs| start + Duration::from_secs(secs);
+        assert_eq!(metrics.entry_rate(at(10), 100), 10.);
+        assert_eq!(metrics.entry_rate(at(40), 400), 10.);
+        // Only the renderings of the last minute count
+        assert_eq!(metrics.entry_rate(at(100), 1000), 10.);
+        assert_eq!(metrics.entry_rate(at(110), 2500), 30.);
+    }
+}
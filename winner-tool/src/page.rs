@@ -0,0 +1,265 @@
+//! Renders the results as a Markdown or self-contained HTML page in the style of
+//! `validators/all.md`, ready to be published as a stage announcement.
+
+use crate::session::ScoringResults;
+use crate::winner::{Category, Winner, Winners};
+use solana_sdk::{native_token::lamports_to_sol, pubkey::Pubkey};
+use std::collections::HashMap;
+
+const KEYBASE_URL: &str = "https://keybase.io";
+
+enum Cell {
+    Text(String),
+    Validator(Pubkey),
+}
+
+enum Block {
+    Heading(usize, String),
+    Paragraph(String),
+    Code(String),
+    Table(Vec<String>, Vec<Vec<Cell>>),
+}
+
+fn winners_table(category: Category, winners: &[Winner]) -> Block {
+    if winners.is_empty() {
+        return Block::Paragraph("None".to_string());
+    }
+    Block::Table(
+        vec![
+            "#".to_string(),
+            "Validator".to_string(),
+            "Score".to_string(),
+        ],
+        winners
+            .iter()
+            .enumerate()
+            .map(|(index, (pubkey, score))| {
+                vec![
+                    Cell::Text((index + 1).to_string()),
+                    Cell::Validator(*pubkey),
+                    Cell::Text(category.describe_score(score)),
+                ]
+            })
+            .collect(),
+    )
+}
+
+// One row per validator with its rank and score in each epoch
+fn epoch_table(winners: &Winners) -> Block {
+    let mut header = vec!["Validator".to_string()];
+    header.extend(
+        winners
+            .epochs
+            .iter()
+            .map(|standings| format!("Epoch {}", standings.epoch)),
+    );
+    Block::Table(
+        header,
+        winners
+            .epoch_table()
+            .into_iter()
+            .map(|(pubkey, ranks)| {
+                let mut row = vec![Cell::Validator(pubkey)];
+                row.extend(ranks.into_iter().map(|rank| {
+                    Cell::Text(match rank {
+                        Some((rank, score)) => format!("{} (#{})", score, rank),
+                        None => "-".to_string(),
+                    })
+                }));
+                row
+            })
+            .collect(),
+    )
+}
+
+fn page_blocks(results: &ScoringResults) -> Vec<Block> {
+    let ScoringResults {
+        first_slot,
+        last_slot,
+        stakes,
+        winners,
+        parameters,
+        ..
+    } = results;
+    let mut blocks = vec![
+        Block::Heading(1, "Tour de SOL Results".to_string()),
+        Block::Paragraph(format!("Slots {} to {}", first_slot, last_slot)),
+    ];
+
+    let mut stakes = stakes.to_vec();
+    stakes.sort_by(|a, b| b.1.cmp(&a.1));
+    let total_stake: u64 = stakes.iter().map(|(_, stake)| stake).sum();
+    blocks.push(Block::Heading(2, "Stake".to_string()));
+    blocks.push(Block::Paragraph(format!(
+        "Total stake: {:.5} SOL ({} lamports)",
+        lamports_to_sol(total_stake),
+        total_stake
+    )));
+    blocks.push(Block::Table(
+        vec!["Validator".to_string(), "Stake".to_string()],
+        stakes
+            .iter()
+            .map(|(pubkey, stake)| {
+                vec![
+                    Cell::Validator(*pubkey),
+                    Cell::Text(format!("{:.5} SOL", lamports_to_sol(*stake))),
+                ]
+            })
+            .collect(),
+    ));
+
+    for winners in winners {
+        blocks.push(Block::Heading(2, winners.category.title.to_string()));
+        if let Some(baseline) = winners.describe_baseline() {
+            blocks.push(Block::Paragraph(baseline));
+        }
+        if !winners.top_winners.is_empty() {
+            blocks.push(Block::Heading(3, "Top Three".to_string()));
+            blocks.push(winners_table(winners.category, &winners.top_winners));
+        }
+        for (bucket_name, bucket) in &winners.bucket_winners {
+            blocks.push(Block::Heading(3, bucket_name.clone()));
+            blocks.push(winners_table(winners.category, bucket));
+        }
+        if !winners.epochs.is_empty() {
+            blocks.push(Block::Heading(3, "By Epoch".to_string()));
+            blocks.push(epoch_table(winners));
+        }
+    }
+
+    blocks.push(Block::Heading(2, "Parameters".to_string()));
+    blocks.push(Block::Code(parameters.to_yaml()));
+    blocks
+}
+
+fn markdown_cell(cell: &Cell, pubkey_map: &HashMap<String, String>) -> String {
+    match cell {
+        Cell::Text(text) => text.replace('|', "\\|"),
+        Cell::Validator(pubkey) => match pubkey_map.get(&pubkey.to_string()) {
+            Some(user) => format!("[{}]({}/{}): `{}`", user, KEYBASE_URL, user, pubkey),
+            None => format!("`{}`", pubkey),
+        },
+    }
+}
+
+/// Renders the results as a Markdown page with keybase profile links
+pub fn render_markdown(results: &ScoringResults, pubkey_map: &HashMap<String, String>) -> String {
+    let mut sections = vec![];
+    for block in page_blocks(results) {
+        sections.push(match block {
+            Block::Heading(level, title) => format!("{} {}", "#".repeat(level), title),
+            Block::Paragraph(text) => text,
+            Block::Code(code) => format!("```yaml\n{}\n```", code.trim_end()),
+            Block::Table(header, rows) => {
+                let mut lines = vec![
+                    format!("| {} |", header.join(" | ")),
+                    format!("|{}", "---|".repeat(header.len())),
+                ];
+                for row in rows {
+                    let cells: Vec<_> = row
+                        .iter()
+                        .map(|cell| markdown_cell(cell, pubkey_map))
+                        .collect();
+                    lines.push(format!("| {} |", cells.join(" | ")));
+                }
+                lines.join("\n")
+            }
+        });
+    }
+    sections.join("\n\n")
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn html_cell(cell: &Cell, pubkey_map: &HashMap<String, String>) -> String {
+    match cell {
+        Cell::Text(text) => html_escape(text),
+        Cell::Validator(pubkey) => match pubkey_map.get(&pubkey.to_string()) {
+            Some(user) => format!(
+                "<a href=\"{}/{}\">{}</a>: <code>{}</code>",
+                KEYBASE_URL,
+                html_escape(user),
+                html_escape(user),
+                pubkey
+            ),
+            None => format!("<code>{}</code>", pubkey),
+        },
+    }
+}
+
+const HTML_STYLE: &str = "body { font-family: sans-serif; margin: 2em auto; max-width: 70em; }
+table { border-collapse: collapse; margin-bottom: 1em; }
+th, td { border: 1px solid #ccc; padding: 0.25em 0.75em; text-align: left; }";
+
+/// Renders the results as a self-contained HTML page with keybase profile links
+pub fn render_html(results: &ScoringResults, pubkey_map: &HashMap<String, String>) -> String {
+    let mut lines = vec![
+        "<!DOCTYPE html>".to_string(),
+        "<html>".to_string(),
+        "<head>".to_string(),
+        "<meta charset=\"utf-8\">".to_string(),
+        "<title>Tour de SOL Results</title>".to_string(),
+        format!("<style>\n{}\n</style>", HTML_STYLE),
+        "</head>".to_string(),
+        "<body>".to_string(),
+    ];
+    for block in page_blocks(results) {
+        match block {
+            Block::Heading(level, title) => {
+                lines.push(format!("<h{0}>{1}</h{0}>", level, html_escape(&title)))
+            }
+            Block::Paragraph(text) => lines.push(format!("<p>{}</p>", html_escape(&text))),
+            Block::Code(code) => lines.push(format!("<pre>{}</pre>", html_escape(&code))),
+            Block::Table(header, rows) => {
+                lines.push("<table>".to_string());
+                let header: Vec<_> = header
+                    .iter()
+                    .map(|title| format!("<th>{}</th>", title))
+                    .collect();
+                lines.push(format!("<tr>{}</tr>", header.concat()));
+                for row in rows {
+                    let cells: Vec<_> = row
+                        .iter()
+                        .map(|cell| format!("<td>{}</td>", html_cell(cell, pubkey_map)))
+                        .collect();
+                    lines.push(format!("<tr>{}</tr>", cells.concat()));
+                }
+                lines.push("</table>".to_string());
+            }
+        }
+    }
+    lines.push("</body>".to_string());
+    lines.push("</html>".to_string());
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_markdown_cell() {
+        let pubkey = Pubkey::new_rand();
+        let mut pubkey_map = HashMap::new();
+        assert_eq!(
+            markdown_cell(&Cell::Validator(pubkey), &pubkey_map),
+            format!("`{}`", pubkey)
+        );
+
+        pubkey_map.insert(pubkey.to_string(), "01node".to_string());
+        assert_eq!(
+            markdown_cell(&Cell::Validator(pubkey), &pubkey_map),
+            format!("[01node](https://keybase.io/01node): `{}`", pubkey)
+        );
+    }
+
+    #[test]
+    fn test_html_escape() {
+        assert_eq!(html_escape("<a & \"b\">"), "&lt;a &amp; &quot;b&quot;&gt;");
+    }
+}
@@ -0,0 +1,488 @@
+//! Checks the inputs of a scoring run against the genesis config and blockstore of the ledger
+//! before replay, so that a misconfigured run fails in seconds instead of after hours of replay.
+
+use crate::error::WinnerToolError;
+use crate::session::ScoringOptions;
+use solana_ledger::blockstore::Blockstore;
+use solana_sdk::{clock::Slot, genesis_config::GenesisConfig, pubkey::Pubkey};
+use solana_vote_program::vote_state::VoteState;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Severity {
+    /// The run would likely report wrong results
+    Warning,
+    /// The run would fail or report meaningless results
+    Error,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Finding {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        write!(f, "{}: {}", severity, self.message)
+    }
+}
+
+/// Findings of the pre-flight checks, in the order they were checked
+#[derive(Debug, Default)]
+pub struct PreflightReport {
+    pub findings: Vec<Finding>,
+}
+
+impl PreflightReport {
+    fn warning(&mut self, message: String) {
+        self.findings.push(Finding {
+            severity: Severity::Warning,
+            message,
+        });
+    }
+
+    fn error(&mut self, message: String) {
+        self.findings.push(Finding {
+            severity: Severity::Error,
+            message,
+        });
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.findings
+            .iter()
+            .any(|finding| finding.severity == Severity::Error)
+    }
+
+    /// Fails with every error of the report
+    pub fn into_result(self) -> Result<(), WinnerToolError> {
+        let errors: Vec<String> = self
+            .findings
+            .into_iter()
+            .filter(|finding| finding.severity == Severity::Error)
+            .map(|finding| finding.message)
+            .collect();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(WinnerToolError::Preflight(errors))
+        }
+    }
+}
+
+// Identities of the validators with a vote account in genesis
+fn genesis_validators(genesis_config: &GenesisConfig) -> BTreeSet<Pubkey> {
+    genesis_config
+        .accounts
+        .values()
+        .filter(|account| account.owner == solana_vote_program::id())
+        .filter_map(VoteState::from)
+        .map(|vote_state| vote_state.node_pubkey)
+        .collect()
+}
+
+fn check_baseline(
+    report: &mut PreflightReport,
+    baseline_validator: Option<&Pubkey>,
+    validators: &BTreeSet<Pubkey>,
+    genesis_accounts: &HashSet<Pubkey>,
+) {
+    let baseline_validator = match baseline_validator {
+        Some(baseline_validator) => baseline_validator,
+        None => {
+            report.error(
+                "No baseline validator is set; pass --baseline-validator or set \
+                 baseline_validator in --config"
+                    .to_string(),
+            );
+            return;
+        }
+    };
+    if validators.contains(baseline_validator) {
+        return;
+    }
+    if genesis_accounts.contains(baseline_validator) {
+        report.warning(format!(
+            "Baseline validator {} has no vote account in genesis, so it must create one before \
+             the window starts",
+            baseline_validator
+        ));
+    } else {
+        report.error(format!(
+            "Baseline validator {} is not in the genesis config of the ledger; check \
+             --baseline-validator or baseline_validator in --config",
+            baseline_validator
+        ));
+    }
+}
+
+fn check_exclusions(
+    report: &mut PreflightReport,
+    excluded_set: &HashSet<Pubkey>,
+    known: &HashSet<Pubkey>,
+) {
+    let mut unknown: Vec<String> = excluded_set
+        .iter()
+        .filter(|pubkey| !known.contains(pubkey))
+        .map(|pubkey| pubkey.to_string())
+        .collect();
+    unknown.sort();
+    for pubkey in unknown {
+        report.warning(format!(
+            "Excluded pubkey {} is neither in genesis nor in the pubkey map; check it for typos",
+            pubkey
+        ));
+    }
+}
+
+// Parent of a slot in the blockstore and whether every shred of the slot has been received
+struct SlotLink {
+    parent_slot: Slot,
+    is_full: bool,
+}
+
+// Checks that `final_slot` is a complete slot whose chain of parents reaches `first_slot`
+fn check_final_slot<F>(report: &mut PreflightReport, final_slot: Slot, first_slot: Slot, link: F)
+where
+    F: Fn(Slot) -> Option<SlotLink>,
+{
+    match link(final_slot) {
+        None => {
+            report.error(format!(
+                "Final slot {} is not in the ledger; check --final-slot or --end-epoch",
+                final_slot
+            ));
+            return;
+        }
+        Some(SlotLink { is_full: false, .. }) => report.error(format!(
+            "Final slot {} is incomplete in the ledger; copy the ledger again or pick an \
+             earlier final slot",
+            final_slot
+        )),
+        Some(_) => (),
+    }
+
+    let mut slot = final_slot;
+    while slot > first_slot {
+        match link(slot) {
+            Some(SlotLink { parent_slot, .. }) if parent_slot != std::u64::MAX => {
+                slot = parent_slot
+            }
+            Some(_) => {
+                report.error(format!(
+                    "Slot {} on the chain of final slot {} has no parent in the ledger, so the \
+                     final slot is not connected to slot {}",
+                    slot, final_slot, first_slot
+                ));
+                return;
+            }
+            None => {
+                report.error(format!(
+                    "Slot {} on the chain of final slot {} is missing from the ledger, so the \
+                     final slot is not connected to slot {}",
+                    slot, final_slot, first_slot
+                ));
+                return;
+            }
+        }
+    }
+}
+
+// Checks that replay reaches the slot of every expected bank hash
+fn check_expected_bank_hashes<F>(
+    report: &mut PreflightReport,
+    slots: &[Slot],
+    final_slot: Option<Slot>,
+    in_ledger: F,
+) where
+    F: Fn(Slot) -> bool,
+{
+    for slot in slots {
+        if final_slot
+            .map(|final_slot| *slot > final_slot)
+            .unwrap_or(false)
+        {
+            report.error(format!(
+                "Slot {} of --expected-bank-hash is after the final slot, so replay never \
+                 reaches it",
+                slot
+            ));
+        } else if !in_ledger(*slot) {
+            report.error(format!(
+                "Slot {} of --expected-bank-hash is not in the ledger; check the slot",
+                slot
+            ));
+        }
+    }
+}
+
+fn check_pubkey_map(
+    report: &mut PreflightReport,
+    validators: &BTreeSet<Pubkey>,
+    excluded_set: &HashSet<Pubkey>,
+    pubkey_map: &HashMap<String, String>,
+) {
+    let mut invalid: Vec<&String> = pubkey_map
+        .keys()
+        .filter(|key| Pubkey::from_str(key).is_err())
+        .collect();
+    invalid.sort();
+    for key in invalid {
+        report.warning(format!("Pubkey map entry {} is not a valid pubkey", key));
+    }
+
+    let ranked: Vec<String> = validators
+        .iter()
+        .filter(|pubkey| !excluded_set.contains(pubkey))
+        .map(|pubkey| pubkey.to_string())
+        .collect();
+    let unnamed: Vec<&str> = ranked
+        .iter()
+        .filter(|pubkey| !pubkey_map.contains_key(*pubkey))
+        .map(|pubkey| pubkey.as_str())
+        .collect();
+    if !unnamed.is_empty() {
+        report.warning(format!(
+            "{} of {} genesis validators have no keybase username in the pubkey map: {}",
+            unnamed.len(),
+            ranked.len(),
+            unnamed.join(", ")
+        ));
+    }
+}
+
+/// Checks `options` and `pubkey_map` against the genesis config and blockstore of the ledger.
+/// The baseline validator is only checked if the run scores the ledger, since an event log is
+/// recorded without one and given one when it is scored.
+pub fn check(
+    genesis_config: &GenesisConfig,
+    blockstore: &Blockstore,
+    options: &ScoringOptions,
+    pubkey_map: &HashMap<String, String>,
+    scoring: bool,
+) -> PreflightReport {
+    let mut report = PreflightReport::default();
+    let validators = genesis_validators(genesis_config);
+    let genesis_accounts: HashSet<Pubkey> = genesis_config.accounts.keys().cloned().collect();
+
+    if scoring {
+        check_baseline(
+            &mut report,
+            options.baseline_validator.as_ref(),
+            &validators,
+            &genesis_accounts,
+        );
+    }
+
+    let mut known = genesis_accounts;
+    known.extend(validators.iter().cloned());
+    known.extend(
+        pubkey_map
+            .keys()
+            .filter_map(|key| Pubkey::from_str(key).ok()),
+    );
+    check_exclusions(&mut report, &options.excluded_set, &known);
+
+    if let Some(final_slot) = options.final_slot {
+        // A ledger that replay bootstraps from a snapshot or checkpoint may start after genesis
+        let first_slot = if options.snapshot_archive.is_some() || options.resume_from.is_some() {
+            blockstore
+                .slot_meta_iterator(0)
+                .ok()
+                .and_then(|mut slot_metas| slot_metas.next())
+                .map(|(slot, _)| slot)
+                .unwrap_or(0)
+        } else {
+            0
+        };
+        check_final_slot(&mut report, final_slot, first_slot, |slot| {
+            blockstore
+                .meta(slot)
+                .ok()
+                .and_then(|meta| meta)
+                .map(|meta| SlotLink {
+                    parent_slot: meta.parent_slot,
+                    is_full: meta.is_full(),
+                })
+        });
+    }
+
+    let expected_slots: Vec<Slot> = options.expected_bank_hashes.keys().cloned().collect();
+    check_expected_bank_hashes(&mut report, &expected_slots, options.final_slot, |slot| {
+        blockstore.meta(slot).ok().and_then(|meta| meta).is_some()
+    });
+
+    check_pubkey_map(&mut report, &validators, &options.excluded_set, pubkey_map);
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::ScoringParameters;
+    use std::collections::BTreeMap;
+
+    fn messages(report: &PreflightReport, severity: Severity) -> Vec<&str> {
+        report
+            .findings
+            .iter()
+            .filter(|finding| finding.severity == severity)
+            .map(|finding| finding.message.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_check_baseline() {
+        let validator = Pubkey::new_rand();
+        let funded = Pubkey::new_rand();
+        let validators: BTreeSet<Pubkey> = vec![validator].into_iter().collect();
+        let genesis_accounts: HashSet<Pubkey> = vec![funded].into_iter().collect();
+
+        let mut report = PreflightReport::default();
+        check_baseline(
+            &mut report,
+            Some(&validator),
+            &validators,
+            &genesis_accounts,
+        );
+        assert!(report.findings.is_empty());
+
+        check_baseline(&mut report, Some(&funded), &validators, &genesis_accounts);
+        assert_eq!(messages(&report, Severity::Warning).len(), 1);
+        assert!(!report.has_errors());
+
+        check_baseline(
+            &mut report,
+            Some(&Pubkey::new_rand()),
+            &validators,
+            &genesis_accounts,
+        );
+        assert!(report.has_errors());
+        check_baseline(&mut report, None, &validators, &genesis_accounts);
+        match report.into_result() {
+            Err(WinnerToolError::Preflight(errors)) => assert_eq!(errors.len(), 2),
+            _ => panic!("expected a pre-flight error"),
+        }
+    }
+
+    #[test]
+    fn test_check_exclusions() {
+        let known_pubkey = Pubkey::new_rand();
+        let unknown_pubkey = Pubkey::new_rand();
+        let known: HashSet<Pubkey> = vec![known_pubkey].into_iter().collect();
+        let excluded_set: HashSet<Pubkey> =
+            vec![known_pubkey, unknown_pubkey].into_iter().collect();
+
+        let mut report = PreflightReport::default();
+        check_exclusions(&mut report, &excluded_set, &known);
+        let warnings = messages(&report, Severity::Warning);
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains(&unknown_pubkey.to_string()));
+    }
+
+    #[test]
+    fn test_check_final_slot() {
+        // 0 <- 1 <- 3 <- 4, 5 is incomplete and 7 is an orphan
+        let mut links = HashMap::new();
+        for (slot, parent_slot, is_full) in &[
+            (1, 0, true),
+            (3, 1, true),
+            (4, 3, true),
+            (5, 4, false),
+            (7, std::u64::MAX, true),
+        ] {
+            links.insert(*slot, (*parent_slot, *is_full));
+        }
+        let link = |slot| {
+            links.get(&slot).map(|(parent_slot, is_full)| SlotLink {
+                parent_slot: *parent_slot,
+                is_full: *is_full,
+            })
+        };
+        let findings = |final_slot, first_slot| {
+            let mut report = PreflightReport::default();
+            check_final_slot(&mut report, final_slot, first_slot, link);
+            report.findings.len()
+        };
+
+        assert_eq!(findings(4, 0), 0);
+        assert_eq!(findings(4, 3), 0);
+        assert_eq!(findings(5, 0), 1);
+        assert_eq!(findings(6, 0), 1);
+        assert_eq!(findings(7, 0), 1);
+    }
+
+    #[test]
+    fn test_check_expected_bank_hashes() {
+        let in_ledger = |slot| slot != 3;
+        let mut report = PreflightReport::default();
+        check_expected_bank_hashes(&mut report, &[1, 3, 7], Some(5), in_ledger);
+        let errors = messages(&report, Severity::Error);
+        assert_eq!(errors.len(), 2);
+        assert!(errors[0].starts_with("Slot 3 "));
+        assert!(errors[1].starts_with("Slot 7 "));
+
+        let mut report = PreflightReport::default();
+        check_expected_bank_hashes(&mut report, &[1, 7], None, in_ledger);
+        assert!(report.findings.is_empty());
+    }
+
+    #[test]
+    fn test_check_pubkey_map() {
+        let named = Pubkey::new_rand();
+        let unnamed = Pubkey::new_rand();
+        let excluded = Pubkey::new_rand();
+        let validators: BTreeSet<Pubkey> = vec![named, unnamed, excluded].into_iter().collect();
+        let excluded_set: HashSet<Pubkey> = vec![excluded].into_iter().collect();
+        let mut pubkey_map = HashMap::new();
+        pubkey_map.insert(named.to_string(), "alice".to_string());
+        pubkey_map.insert("not-a-pubkey".to_string(), "bob".to_string());
+
+        let mut report = PreflightReport::default();
+        check_pubkey_map(&mut report, &validators, &excluded_set, &pubkey_map);
+        let warnings = messages(&report, Severity::Warning);
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].contains("not-a-pubkey"));
+        assert!(warnings[1].starts_with("1 of 2 genesis validators"));
+        assert!(warnings[1].contains(&unnamed.to_string()));
+    }
+
+    #[test]
+    fn test_check_record() {
+        let ledger_dir = tempfile::tempdir().unwrap();
+        let blockstore = Blockstore::open(ledger_dir.path()).unwrap();
+        let genesis_config = GenesisConfig::default();
+        let options = ScoringOptions {
+            baseline_validator: None,
+            excluded_set: HashSet::new(),
+            starting_balance: 0,
+            start_slot: None,
+            final_slot: None,
+            parameters: ScoringParameters::default(),
+            checkpoint_path: None,
+            checkpoint_interval: 0,
+            resume_from: None,
+            snapshot_archive: None,
+            replay_threads: None,
+            verify_poh: false,
+            expected_bank_hashes: BTreeMap::new(),
+            metrics: None,
+        };
+        let pubkey_map = HashMap::new();
+
+        // Recording an event log does not need a baseline validator
+        let report = check(&genesis_config, &blockstore, &options, &pubkey_map, false);
+        assert!(!report.has_errors());
+
+        let report = check(&genesis_config, &blockstore, &options, &pubkey_map, true);
+        assert_eq!(messages(&report, Severity::Error).len(), 1);
+        assert!(messages(&report, Severity::Error)[0].starts_with("No baseline validator"));
+    }
+}
@@ -0,0 +1,175 @@
+//! Progress of a replay while it runs, and the time spent in each phase of a run once it is done.
+
+use log::*;
+use solana_ledger::blockstore::Blockstore;
+use solana_sdk::clock::Slot;
+use std::time::{Duration, Instant};
+
+// Minimum time between two progress messages
+const REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Last slot of the blockstore, which replay reaches unless it halts at a final slot. Only the
+/// slots from the last root on are scanned, so that a long ledger is not read from genesis.
+pub fn last_blockstore_slot(blockstore: &Blockstore) -> Option<Slot> {
+    blockstore
+        .slot_meta_iterator(blockstore.last_root())
+        .ok()?
+        .map(|(slot, _meta)| slot)
+        .last()
+}
+
+/// Logs how far replay has come towards `last_slot`, how fast it goes and when it should be done
+pub struct ReplayProgress {
+    last_slot: Option<Slot>,
+    start: Instant,
+    last_report: Instant,
+    first_slot: Option<Slot>,
+}
+
+impl ReplayProgress {
+    pub fn new(last_slot: Option<Slot>) -> Self {
+        let now = Instant::now();
+        Self {
+            last_slot,
+            start: now,
+            last_report: now,
+            first_slot: None,
+        }
+    }
+
+    /// Observes the slot of the bank after a batch of entries was processed
+    pub fn observe(&mut self, slot: Slot) {
+        let first_slot = *self.first_slot.get_or_insert(slot);
+        let now = Instant::now();
+        if now.duration_since(self.last_report) >= REPORT_INTERVAL {
+            self.last_report = now;
+            let elapsed = now.duration_since(self.start);
+            info!(
+                "{}",
+                progress_message(slot, first_slot, self.last_slot, elapsed)
+            );
+        }
+    }
+}
+
+fn progress_message(
+    slot: Slot,
+    first_slot: Slot,
+    last_slot: Option<Slot>,
+    elapsed: Duration,
+) -> String {
+    let replayed_slots = slot.saturating_sub(first_slot);
+    let slots_per_sec = replayed_slots as f64 / elapsed.as_secs_f64().max(1.);
+    match last_slot {
+        Some(last_slot) if last_slot > slot => {
+            let percent = 100. * replayed_slots as f64 / (last_slot - first_slot) as f64;
+            let eta = if slots_per_sec > 0. {
+                let remaining_secs = (last_slot - slot) as f64 / slots_per_sec;
+                format_duration(Duration::from_secs_f64(remaining_secs))
+            } else {
+                "unknown".to_string()
+            };
+            format!(
+                "Replayed slot {} of {} ({:.1}%), {:.1} slots/s, ETA {}",
+                slot, last_slot, percent, slots_per_sec, eta
+            )
+        }
+        _ => format!("Replayed slot {}, {:.1} slots/s", slot, slots_per_sec),
+    }
+}
+
+/// Formats `duration` as hours, minutes and seconds
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+/// Time spent in each phase of a run, in the order the phases ran
+#[derive(Clone, Debug, Default)]
+pub struct PhaseTimings {
+    phases: Vec<(String, Duration)>,
+}
+
+impl PhaseTimings {
+    pub fn record(&mut self, phase: &str, duration: Duration) {
+        self.phases.push((phase.to_string(), duration));
+    }
+
+    /// Runs `f` as `phase`, returning its result
+    pub fn time<T, F: FnOnce() -> T>(&mut self, phase: &str, f: F) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.record(phase, start.elapsed());
+        result
+    }
+
+    pub fn phases(&self) -> &[(String, Duration)] {
+        &self.phases
+    }
+
+    /// One line per phase, followed by the total
+    pub fn summary(&self) -> String {
+        let width = self
+            .phases
+            .iter()
+            .map(|(phase, _)| phase.len())
+            .max()
+            .unwrap_or(0)
+            .max("total".len());
+        let mut lines: Vec<String> = self
+            .phases
+            .iter()
+            .map(|(phase, duration)| {
+                format!(
+                    "  {:width$}  {}",
+                    phase,
+                    format_duration(*duration),
+                    width = width
+                )
+            })
+            .collect();
+        let total: Duration = self.phases.iter().map(|(_, duration)| *duration).sum();
+        lines.push(format!(
+            "  {:width$}  {}",
+            "total",
+            format_duration(total),
+            width = width
+        ));
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress_message() {
+        assert_eq!(
+            progress_message(300, 100, Some(1100), Duration::from_secs(100)),
+            "Replayed slot 300 of 1100 (20.0%), 2.0 slots/s, ETA 0:06:40"
+        );
+        assert_eq!(
+            progress_message(300, 100, None, Duration::from_secs(100)),
+            "Replayed slot 300, 2.0 slots/s"
+        );
+        assert_eq!(
+            progress_message(100, 100, Some(200), Duration::from_secs(5)),
+            "Replayed slot 100 of 200 (0.0%), 0.0 slots/s, ETA unknown"
+        );
+    }
+
+    #[test]
+    fn test_phase_timings() {
+        let mut timings = PhaseTimings::default();
+        timings.record("replay", Duration::from_secs(3725));
+        assert_eq!(timings.time("latency", || 42), 42);
+        assert_eq!(timings.phases().len(), 2);
+        assert_eq!(timings.phases()[1].0, "latency");
+        assert_eq!(
+            timings.summary().lines().next().unwrap(),
+            "  replay   1:02:05"
+        );
+        assert!(timings.summary().ends_with("  total    1:02:05"));
+    }
+}
@@ -0,0 +1,436 @@
+//! Structured report of the winner tool results for consumption by other tools.
+
+use crate::session::{ScoringParameters, ScoringResults};
+use crate::winner::{Score, Winner};
+use serde::Serialize;
+use solana_sdk::{
+    clock::{Epoch, Slot},
+    pubkey::Pubkey,
+};
+use std::collections::HashMap;
+
+#[derive(Serialize)]
+pub struct Report {
+    pub first_slot: Slot,
+    pub last_slot: Slot,
+    pub parameters: ScoringParameters,
+    pub total_stake: u64,
+    pub stakes: Vec<StakeEntry>,
+    pub categories: Vec<CategoryReport>,
+}
+
+#[derive(Serialize)]
+pub struct StakeEntry {
+    pub identity: String,
+    pub keybase: Option<String>,
+    pub lamports: u64,
+}
+
+#[derive(Serialize)]
+pub struct CategoryReport {
+    pub category: &'static str,
+    pub title: &'static str,
+    pub baseline: Option<Score>,
+    pub top_winners: Vec<WinnerEntry>,
+    pub buckets: Vec<BucketReport>,
+    pub scores: Vec<ScoreEntry>,
+    pub epochs: Vec<EpochReport>,
+}
+
+#[derive(Serialize)]
+pub struct EpochReport {
+    pub epoch: Epoch,
+    /// Scores within the epoch, sorted from best to worst
+    pub scores: Vec<ScoreEntry>,
+}
+
+#[derive(Serialize)]
+pub struct BucketReport {
+    pub name: String,
+    pub winners: Vec<WinnerEntry>,
+}
+
+#[derive(Serialize)]
+pub struct WinnerEntry {
+    pub identity: String,
+    pub keybase: Option<String>,
+    pub score: Score,
+    pub description: String,
+}
+
+#[derive(Serialize)]
+pub struct ScoreEntry {
+    pub identity: String,
+    pub keybase: Option<String>,
+    pub score: Score,
+}
+
+/// Standing of a single validator identity in every category
+#[derive(Serialize)]
+pub struct ValidatorStanding {
+    pub identity: String,
+    pub keybase: Option<String>,
+    pub stake: Option<u64>,
+    pub categories: Vec<CategoryStanding>,
+}
+
+#[derive(Serialize)]
+pub struct CategoryStanding {
+    pub category: &'static str,
+    /// Position in the category's results, starting at 1
+    pub rank: usize,
+    pub score: Score,
+    pub bucket: Option<String>,
+}
+
+impl Report {
+    pub fn new(results: &ScoringResults, pubkey_map: &HashMap<String, String>) -> Self {
+        let ScoringResults {
+            first_slot,
+            last_slot,
+            stakes,
+            winners,
+            parameters,
+            ..
+        } = results;
+        let keybase = |pubkey: &Pubkey| pubkey_map.get(&pubkey.to_string()).cloned();
+
+        let total_stake = stakes.iter().map(|(_, stake)| stake).sum();
+        let stakes = stakes
+            .iter()
+            .map(|(pubkey, stake)| StakeEntry {
+                identity: pubkey.to_string(),
+                keybase: keybase(pubkey),
+                lamports: *stake,
+            })
+            .collect();
+
+        let categories = winners
+            .iter()
+            .map(|winners| {
+                let category = winners.category;
+                let score_entries = |winners: &[Winner]| -> Vec<ScoreEntry> {
+                    winners
+                        .iter()
+                        .map(|(pubkey, score)| ScoreEntry {
+                            identity: pubkey.to_string(),
+                            keybase: keybase(pubkey),
+                            score: *score,
+                        })
+                        .collect()
+                };
+                let winner_entries = |winners: &[Winner]| -> Vec<WinnerEntry> {
+                    winners
+                        .iter()
+                        .map(|(pubkey, score)| WinnerEntry {
+                            identity: pubkey.to_string(),
+                            keybase: keybase(pubkey),
+                            score: *score,
+                            description: category.describe_score(score),
+                        })
+                        .collect()
+                };
+
+                CategoryReport {
+                    category: category.name,
+                    title: category.title,
+                    baseline: winners.baseline,
+                    top_winners: winner_entries(&winners.top_winners),
+                    buckets: winners
+                        .bucket_winners
+                        .iter()
+                        .map(|(name, bucket)| BucketReport {
+                            name: name.clone(),
+                            winners: winner_entries(bucket),
+                        })
+                        .collect(),
+                    scores: score_entries(&winners.results),
+                    epochs: winners
+                        .epochs
+                        .iter()
+                        .map(|standings| EpochReport {
+                            epoch: standings.epoch,
+                            scores: score_entries(&standings.results),
+                        })
+                        .collect(),
+                }
+            })
+            .collect();
+
+        Report {
+            first_slot: *first_slot,
+            last_slot: *last_slot,
+            parameters: parameters.clone(),
+            total_stake,
+            stakes,
+            categories,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    /// Standing of the validator with the identity or keybase username `key`
+    pub fn validator_standing(&self, key: &str) -> Option<ValidatorStanding> {
+        let is_validator = |identity: &str, keybase: &Option<String>| {
+            identity == key || keybase.as_ref().map(String::as_str) == Some(key)
+        };
+
+        let mut validator = None;
+        let mut stake = None;
+        if let Some(entry) = self
+            .stakes
+            .iter()
+            .find(|entry| is_validator(&entry.identity, &entry.keybase))
+        {
+            validator = Some((entry.identity.clone(), entry.keybase.clone()));
+            stake = Some(entry.lamports);
+        }
+
+        let mut categories = vec![];
+        for category in &self.categories {
+            let position = category
+                .scores
+                .iter()
+                .position(|entry| is_validator(&entry.identity, &entry.keybase));
+            if let Some(index) = position {
+                let entry = &category.scores[index];
+                validator.get_or_insert_with(|| (entry.identity.clone(), entry.keybase.clone()));
+                let bucket = category
+                    .buckets
+                    .iter()
+                    .find(|bucket| {
+                        bucket
+                            .winners
+                            .iter()
+                            .any(|winner| winner.identity == entry.identity)
+                    })
+                    .map(|bucket| bucket.name.clone());
+                categories.push(CategoryStanding {
+                    category: category.category,
+                    rank: index + 1,
+                    score: entry.score,
+                    bucket,
+                });
+            }
+        }
+
+        let (identity, keybase) = validator?;
+        Some(ValidatorStanding {
+            identity,
+            keybase,
+            stake,
+            categories,
+        })
+    }
+}
+
+fn raw_score(score: &Score) -> String {
+    match score {
+        Score::Lamports(value) | Score::Points(value) => value.to_string(),
+        Score::Ratio(ratio) => ratio.to_string(),
+    }
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains(|c: char| c == ',' || c == '"' || c == '\n') {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn csv_row(fields: &[String]) -> String {
+    fields
+        .iter()
+        .map(|field| csv_field(field))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Renders one row per validator identity with its stake and the raw measurements, score,
+/// bucket and per-epoch scores of every category.
+pub fn render_csv(results: &ScoringResults, pubkey_map: &HashMap<String, String>) -> String {
+    let ScoringResults {
+        stakes, winners, ..
+    } = results;
+    let stake_map: HashMap<Pubkey, u64> = stakes.iter().cloned().collect();
+
+    let mut identities: Vec<Pubkey> = stakes.iter().map(|(pubkey, _)| *pubkey).collect();
+    for winners in winners {
+        identities.extend(winners.results.iter().map(|(pubkey, _)| *pubkey));
+    }
+    identities.sort_by_key(|pubkey| pubkey.to_string());
+    identities.dedup();
+
+    struct CategoryColumns<'a> {
+        detail_names: Vec<&'static str>,
+        scores: HashMap<Pubkey, Score>,
+        buckets: HashMap<Pubkey, &'a str>,
+        epoch_scores: Vec<HashMap<Pubkey, Score>>,
+    }
+
+    let categories: Vec<CategoryColumns> = winners
+        .iter()
+        .map(|winners| {
+            let mut buckets = HashMap::new();
+            for (bucket_name, bucket) in &winners.bucket_winners {
+                for (pubkey, _) in bucket {
+                    buckets.insert(*pubkey, bucket_name.as_str());
+                }
+            }
+            CategoryColumns {
+                detail_names: winners
+                    .details
+                    .values()
+                    .next()
+                    .map(|details| details.iter().map(|(name, _)| *name).collect())
+                    .unwrap_or_default(),
+                scores: winners.results.iter().cloned().collect(),
+                buckets,
+                epoch_scores: winners
+                    .epochs
+                    .iter()
+                    .map(|standings| standings.results.iter().cloned().collect())
+                    .collect(),
+            }
+        })
+        .collect();
+
+    let mut header = vec![
+        "identity".to_string(),
+        "keybase".to_string(),
+        "stake_lamports".to_string(),
+    ];
+    for (winners, columns) in winners.iter().zip(categories.iter()) {
+        let name = winners.category.name;
+        for detail_name in &columns.detail_names {
+            header.push(format!("{}_{}", name, detail_name));
+        }
+        header.push(format!("{}_score", name));
+        header.push(format!("{}_bucket", name));
+        for standings in &winners.epochs {
+            header.push(format!("{}_epoch_{}", name, standings.epoch));
+        }
+    }
+
+    let mut lines = vec![csv_row(&header)];
+    for pubkey in identities {
+        let mut row = vec![
+            pubkey.to_string(),
+            pubkey_map
+                .get(&pubkey.to_string())
+                .cloned()
+                .unwrap_or_default(),
+            stake_map
+                .get(&pubkey)
+                .map(|stake| stake.to_string())
+                .unwrap_or_default(),
+        ];
+        for (winners, columns) in winners.iter().zip(categories.iter()) {
+            let details = winners.details.get(&pubkey);
+            for detail_name in &columns.detail_names {
+                row.push(
+                    details
+                        .and_then(|details| details.iter().find(|(name, _)| name == detail_name))
+                        .map(|(_, value)| value.to_string())
+                        .unwrap_or_default(),
+                );
+            }
+            row.push(
+                columns
+                    .scores
+                    .get(&pubkey)
+                    .map(raw_score)
+                    .unwrap_or_default(),
+            );
+            row.push(
+                columns
+                    .buckets
+                    .get(&pubkey)
+                    .map(|bucket| bucket.to_string())
+                    .unwrap_or_default(),
+            );
+            for scores in &columns.epoch_scores {
+                row.push(scores.get(&pubkey).map(raw_score).unwrap_or_default());
+            }
+        }
+        lines.push(csv_row(&row));
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_field() {
+        assert_eq!(csv_field("Top 25%"), "Top 25%");
+        assert_eq!(csv_field("95% to 75%, of"), "\"95% to 75%, of\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn test_validator_standing() {
+        let entry = |identity: &str, keybase: Option<&str>, score: i64| ScoreEntry {
+            identity: identity.to_string(),
+            keybase: keybase.map(str::to_string),
+            score: Score::Points(score),
+        };
+        let report = Report {
+            first_slot: 0,
+            last_slot: 10,
+            parameters: ScoringParameters::default(),
+            total_stake: 100,
+            stakes: vec![StakeEntry {
+                identity: "validator1".to_string(),
+                keybase: Some("alice".to_string()),
+                lamports: 100,
+            }],
+            categories: vec![CategoryReport {
+                category: "latency",
+                title: "Confirmation Latency",
+                baseline: None,
+                top_winners: vec![],
+                buckets: vec![BucketReport {
+                    name: "Top 25%".to_string(),
+                    winners: vec![WinnerEntry {
+                        identity: "validator2".to_string(),
+                        keybase: None,
+                        score: Score::Points(9),
+                        description: String::new(),
+                    }],
+                }],
+                scores: vec![
+                    entry("validator2", None, 9),
+                    entry("validator1", Some("alice"), 5),
+                ],
+                epochs: vec![],
+            }],
+        };
+
+        let standing = report.validator_standing("alice").unwrap();
+        assert_eq!(standing.identity, "validator1");
+        assert_eq!(standing.stake, Some(100));
+        assert_eq!(standing.categories.len(), 1);
+        assert_eq!(standing.categories[0].rank, 2);
+        assert_eq!(standing.categories[0].bucket, None);
+
+        // Validators without stake are found by their scores
+        let standing = report.validator_standing("validator2").unwrap();
+        assert_eq!(standing.stake, None);
+        assert_eq!(standing.categories[0].rank, 1);
+        assert_eq!(standing.categories[0].bucket, Some("Top 25%".to_string()));
+
+        assert!(report.validator_standing("unknown").is_none());
+    }
+
+    #[test]
+    fn test_raw_score() {
+        assert_eq!(raw_score(&Score::Lamports(-100)), "-100");
+        assert_eq!(raw_score(&Score::Ratio(0.5)), "0.5");
+        assert_eq!(raw_score(&Score::Points(7)), "7");
+    }
+}
@@ -2,84 +2,102 @@
 //! balances of all stake and vote accounts attributed to a particular validator.
 //!
 //! The top 3 validators will receive the top prizes and validators will be awarded additional
-//! prizes if they place into the following buckets:
+//! prizes if they place into the following buckets (with the default `bucket_percentiles`):
 //!
 //! `high` - Top 25%
 //! `medium` - Top 25-50%
 //! `low` - Top 50-90%
 //! `bottom` - Bottom 10%
 
-use crate::winner::{self, Winner, Winners};
-use solana_runtime::bank::Bank;
-use solana_sdk::{account::Account, native_token::lamports_to_sol, pubkey::Pubkey};
-use solana_stake_program::stake_state::Delegation;
-use solana_vote_program::vote_state::VoteState;
-use std::cmp::{max, min};
+use crate::category::{Evaluation, ScoringCategory, ScoringContext};
+use crate::checkpoint;
+use crate::error::WinnerToolError;
+use crate::events::{AccountsState, VoteAccountState};
+use crate::utils::{self, EpochTotals};
+use crate::winner::{Category, Score, Winner};
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use std::cmp::max;
 use std::collections::{HashMap, HashSet};
 
-const HIGH_BUCKET: &str = "Top 25%";
-const MEDUIM_BUCKET: &str = "25% to 50%";
-const LOW_BUCKET: &str = "50% to 90%";
-const BOTTOM_BUCKET: &str = "Bottom 10%";
-
-fn voter_stake_rewards(stake_delegations: HashMap<Pubkey, Delegation>) -> HashMap<Pubkey, u64> {
-    let mut voter_stake_sum: HashMap<Pubkey, u64> = HashMap::new();
-    for (_key, delegation) in stake_delegations {
-        voter_stake_sum
-            .entry(delegation.voter_pubkey)
-            .and_modify(|stake_sum| *stake_sum += delegation.stake)
-            .or_insert(delegation.stake);
+/// Percentiles of the ranked validators that separate the high, medium, low and bottom buckets
+pub type BucketPercentiles = [u64; 3];
+
+const DEFAULT_BUCKET_PERCENTILES: BucketPercentiles = [25, 50, 90];
+
+/// Tunable parameters of the rewards earned category
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Parameters {
+    pub bucket_percentiles: BucketPercentiles,
+}
+
+impl Default for Parameters {
+    fn default() -> Self {
+        Self {
+            bucket_percentiles: DEFAULT_BUCKET_PERCENTILES,
+        }
+    }
+}
+
+pub fn validate_bucket_percentiles(percentiles: &BucketPercentiles) -> Result<(), WinnerToolError> {
+    if percentiles.windows(2).all(|pair| pair[0] <= pair[1]) && percentiles[2] <= 100 {
+        Ok(())
+    } else {
+        Err(WinnerToolError::InvalidArgument(format!(
+            "Bucket percentiles {:?} must be in ascending order and at most 100",
+            percentiles
+        )))
     }
-    voter_stake_sum
 }
 
+// Rewards earned by each validator since its starting balance, which is taken from
+// `start_balances` when the validator was present at the start of the window
 fn validator_results(
     validator_reward_map: HashMap<Pubkey, u64>,
     excluded_set: &HashSet<Pubkey>,
+    start_balances: &HashMap<Pubkey, u64>,
     starting_balance: u64,
 ) -> Vec<(Pubkey, i64)> {
-    let mut validator_rewards: Vec<(Pubkey, u64)> = validator_reward_map
+    let mut results: Vec<(Pubkey, i64)> = validator_reward_map
         .iter()
         .filter(|(key, _)| !excluded_set.contains(key))
-        .map(|(key, balance)| (*key, *balance))
+        .map(|(key, balance)| {
+            let start_balance = start_balances.get(key).cloned().unwrap_or(starting_balance);
+            (*key, (*balance as i64) - (start_balance as i64))
+        })
         .collect();
 
-    // Sort descending and calculate results
-    validator_rewards.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
-    validator_rewards
-        .into_iter()
-        .map(|(key, earned)| (key, (earned as i64) - (starting_balance as i64)))
-        .collect()
+    // Sort descending
+    results.sort_by(|a, b| b.1.cmp(&a.1));
+    results
 }
 
-fn validator_rewards(
-    mut voter_stake_rewards: HashMap<Pubkey, u64>,
-    vote_accounts: HashMap<Pubkey, (u64, Account)>,
-) -> HashMap<Pubkey, u64> {
+fn validator_rewards(vote_accounts: &HashMap<Pubkey, VoteAccountState>) -> HashMap<Pubkey, u64> {
     // Sum validator earned reward totals (stake rewards + commission)
     let mut validator_reward_map: HashMap<Pubkey, u64> = HashMap::new();
-    for (voter_key, (_stake, account)) in vote_accounts {
-        if let Some(vote_state) = VoteState::from(&account) {
-            let voter_commission = account.lamports;
-            let voter_stake_reward = voter_stake_rewards.remove(&voter_key).unwrap_or_default();
-
-            let validator_id = vote_state.node_pubkey;
-            validator_reward_map
-                .entry(validator_id)
-                .and_modify(|validator_reward| {
-                    // If multiple vote accounts are detected, take the max
-                    *validator_reward =
-                        max(*validator_reward, voter_commission + voter_stake_reward);
-                })
-                .or_insert(voter_commission + voter_stake_reward);
-        }
+    for vote_account in vote_accounts.values() {
+        let voter_commission = vote_account.lamports;
+        let voter_stake_reward = vote_account.delegated_stake;
+
+        let validator_id = vote_account.node_pubkey;
+        validator_reward_map
+            .entry(validator_id)
+            .and_modify(|validator_reward| {
+                // If multiple vote accounts are detected, take the max
+                *validator_reward = max(*validator_reward, voter_commission + voter_stake_reward);
+            })
+            .or_insert(voter_commission + voter_stake_reward);
     }
 
     validator_reward_map
 }
 
 // Bucket validators for reward distribution
-fn bucket_winners(results: &[(Pubkey, i64)]) -> Vec<(String, Vec<Winner>)> {
+fn bucket_winners(
+    results: &[Winner],
+    percentiles: &BucketPercentiles,
+) -> Vec<(String, Vec<Winner>)> {
     let num_validators = results.len();
     let mut bucket_winners = Vec::new();
 
@@ -91,25 +109,33 @@ fn bucket_winners(results: &[(Pubkey, i64)]) -> Vec<(String, Vec<Winner>)> {
         index
     };
 
-    // Top 25% of validators
-    let high_bucket_index = handle_ties(max(1, num_validators / 4) - 1);
+    // Last index within the top `percentile` percent of validators
+    let percentile_index = |percentile: u64| max(1, num_validators * percentile as usize / 100) - 1;
+    let [high_percentile, medium_percentile, low_percentile] = *percentiles;
+
+    // Top 25% of validators by default
+    let high_bucket_index = handle_ties(percentile_index(high_percentile));
     let high = &results[..=high_bucket_index];
-    bucket_winners.push((HIGH_BUCKET.to_string(), normalize_winners(high)));
+    let high_bucket = format!("Top {}%", high_percentile);
+    bucket_winners.push((high_bucket, high.to_vec()));
 
-    // Top 25-50% of validators
-    let medium_bucket_index = handle_ties(max(1, num_validators / 2) - 1);
+    // Top 25-50% of validators by default
+    let medium_bucket_index = handle_ties(percentile_index(medium_percentile));
     let medium = &results[(high_bucket_index + 1)..=medium_bucket_index];
-    bucket_winners.push((MEDUIM_BUCKET.to_string(), normalize_winners(medium)));
+    let medium_bucket = format!("{}% to {}%", high_percentile, medium_percentile);
+    bucket_winners.push((medium_bucket, medium.to_vec()));
 
-    // Top 50-90% of validators
-    let low_bucket_index = handle_ties(max(1, 9 * num_validators / 10) - 1);
+    // Top 50-90% of validators by default
+    let low_bucket_index = handle_ties(percentile_index(low_percentile));
     let low = &results[(medium_bucket_index + 1)..=low_bucket_index];
-    bucket_winners.push((LOW_BUCKET.to_string(), normalize_winners(low)));
+    let low_bucket = format!("{}% to {}%", medium_percentile, low_percentile);
+    bucket_winners.push((low_bucket, low.to_vec()));
 
-    // Bottom 10% of validators
+    // Bottom 10% of validators by default
     let bottom_bucket_index = handle_ties(max(1, num_validators) - 1);
     let bottom = &results[(low_bucket_index + 1)..=bottom_bucket_index];
-    bucket_winners.push((BOTTOM_BUCKET.to_string(), normalize_winners(bottom)));
+    let bottom_bucket = format!("Bottom {}%", 100 - low_percentile);
+    bucket_winners.push((bottom_bucket, bottom.to_vec()));
 
     bucket_winners
 }
@@ -117,50 +143,126 @@ fn bucket_winners(results: &[(Pubkey, i64)]) -> Vec<(String, Vec<Winner>)> {
 fn normalize_winners(winners: &[(Pubkey, i64)]) -> Vec<Winner> {
     winners
         .iter()
-        .map(|(key, earned)| {
-            let mut earned = *earned;
-            let mut sign = "";
-            if earned < 0 {
-                sign = "-";
-                earned = -earned;
-            }
-            (
-                *key,
-                format!(
-                    "Earned {}{:.5} SOL ({}{} lamports) in stake rewards and commission",
-                    sign,
-                    lamports_to_sol(earned as u64),
-                    sign,
-                    earned
-                ),
-            )
-        })
+        .map(|(key, earned)| (*key, Score::Lamports(*earned)))
         .collect()
 }
 
-pub fn compute_winners(
-    bank: &Bank,
-    excluded_set: &HashSet<Pubkey>,
+pub const CATEGORY: Category = Category {
+    name: "rewards_earned",
+    title: "Most Rewards Earned",
+    describe: describe_score,
+};
+
+fn describe_score(score: &Score) -> String {
+    format!("Earned {} in stake rewards and commission", score)
+}
+
+// Sums the rewards earned by every validator up to the state of `accounts`
+fn bank_rewards(accounts: &AccountsState) -> HashMap<Pubkey, u64> {
+    validator_rewards(&accounts.vote_accounts)
+}
+
+fn signed_totals(validator_reward_map: &HashMap<Pubkey, u64>) -> HashMap<Pubkey, i64> {
+    validator_reward_map
+        .iter()
+        .map(|(key, balance)| (*key, *balance as i64))
+        .collect()
+}
+
+// Balances recorded during replay
+#[derive(Default, Deserialize, Serialize)]
+struct State {
+    start_balances: HashMap<Pubkey, u64>,
+    epoch_totals: EpochTotals,
+}
+
+pub struct RewardsEarned {
     starting_balance: u64,
-) -> Winners {
-    let voter_stake_rewards = voter_stake_rewards(bank.stake_delegations());
-    let validator_reward_map = validator_rewards(voter_stake_rewards, bank.vote_accounts());
-    let results = validator_results(validator_reward_map, excluded_set, starting_balance);
-    let num_validators = results.len();
-    let num_winners = min(num_validators, 3);
-    assert!(num_winners > 0);
+    parameters: Parameters,
+    state: State,
+}
+
+impl RewardsEarned {
+    pub fn new(starting_balance: u64, parameters: Parameters) -> Self {
+        Self {
+            starting_balance,
+            parameters,
+            state: State::default(),
+        }
+    }
+}
+
+impl ScoringCategory for RewardsEarned {
+    fn category(&self) -> Category {
+        CATEGORY
+    }
+
+    fn on_window_start(&mut self, accounts: &AccountsState) {
+        self.state.start_balances = bank_rewards(accounts);
+    }
+
+    fn on_epoch_start(&mut self, accounts: &AccountsState) {
+        if self.state.epoch_totals.is_new_epoch(accounts.epoch) {
+            let totals = signed_totals(&bank_rewards(accounts));
+            self.state.epoch_totals.record(accounts.epoch, totals);
+        }
+    }
+
+    fn save_state(&self) -> Result<Vec<u8>, WinnerToolError> {
+        checkpoint::serialize_state(&self.state)
+    }
+
+    fn restore_state(&mut self, state: &[u8]) -> Result<(), WinnerToolError> {
+        self.state = checkpoint::deserialize_state(state)?;
+        Ok(())
+    }
 
-    Winners {
-        category: winner::Category::RewardsEarned,
-        top_winners: normalize_winners(&results[..num_winners]),
-        bucket_winners: bucket_winners(&results),
+    fn evaluate(&self, context: &ScoringContext) -> Result<Evaluation, WinnerToolError> {
+        let validator_reward_map = bank_rewards(context.accounts);
+        let epoch_scores = self
+            .state
+            .epoch_totals
+            .deltas(
+                &signed_totals(&validator_reward_map),
+                self.starting_balance as i64,
+            )
+            .into_iter()
+            .map(|(epoch, deltas)| {
+                let scores = deltas
+                    .into_iter()
+                    .map(|(key, earned)| (key, Score::Lamports(earned)))
+                    .collect();
+                (epoch, scores)
+            })
+            .collect();
+
+        let results = validator_results(
+            validator_reward_map,
+            context.excluded_set,
+            &self.state.start_balances,
+            self.starting_balance,
+        );
+        if results.is_empty() {
+            return Err(WinnerToolError::NoResults(CATEGORY.name));
+        }
+
+        Ok(Evaluation {
+            results: normalize_winners(&results),
+            baseline: None,
+            details: HashMap::new(),
+            epochs: utils::epoch_standings(epoch_scores, context.excluded_set),
+        })
+    }
+
+    fn bucket_winners(&self, evaluation: &Evaluation) -> Vec<(String, Vec<Winner>)> {
+        bucket_winners(&evaluation.results, &self.parameters.bucket_percentiles)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use solana_vote_program::vote_state::VoteInit;
+    use crate::events::LeaderSlots;
 
     #[test]
     fn test_validator_results() {
@@ -178,23 +280,26 @@ mod tests {
             set
         };
 
-        let results = validator_results(rewards_map, &excluded_set, 100);
+        let results = validator_results(rewards_map.clone(), &excluded_set, &HashMap::new(), 100);
         assert_eq!(results[0], (top_validator, 900));
         assert_eq!(results[1], (bottom_validator, -90));
+
+        // Validators present at the start of the window are measured from their balance then
+        let mut start_balances = HashMap::new();
+        start_balances.insert(top_validator, 995);
+        let results = validator_results(rewards_map, &excluded_set, &start_balances, 100);
+        assert_eq!(results[0], (top_validator, 5));
+        assert_eq!(results[1], (bottom_validator, -90));
     }
 
     #[test]
     fn test_validator_rewards() {
-        let new_vote_account = |lamports: u64, validator_id: &Pubkey| -> Account {
-            Account::new_data(
+        let new_vote_account = |lamports: u64, validator_id: &Pubkey| -> VoteAccountState {
+            VoteAccountState {
+                node_pubkey: *validator_id,
                 lamports,
-                &VoteState::new(&VoteInit {
-                    node_pubkey: validator_id.clone(),
-                    ..VoteInit::default()
-                }),
-                &Pubkey::new_rand(),
-            )
-            .unwrap()
+                ..VoteAccountState::default()
+            }
         };
 
         let validator1 = Pubkey::new_rand();
@@ -202,15 +307,10 @@ mod tests {
 
         let mut vote_accounts = HashMap::new();
         let voter1 = Pubkey::new_rand();
-        vote_accounts.insert(voter1.clone(), (0, new_vote_account(100, &validator1)));
-        vote_accounts.insert(Pubkey::new_rand(), (0, new_vote_account(100, &validator2)));
-        vote_accounts.insert(Pubkey::new_rand(), (0, new_vote_account(200, &validator2)));
-
-        let voter_stake_rewards = {
-            let mut map = HashMap::new();
-            map.insert(voter1, 1000);
-            map
-        };
+        vote_accounts.insert(voter1, new_vote_account(100, &validator1));
+        vote_accounts.insert(Pubkey::new_rand(), new_vote_account(100, &validator2));
+        vote_accounts.insert(Pubkey::new_rand(), new_vote_account(200, &validator2));
+        vote_accounts.get_mut(&voter1).unwrap().delegated_stake = 1000;
 
         let expected_rewards = {
             let mut map = HashMap::new();
@@ -219,47 +319,7 @@ mod tests {
             map
         };
 
-        assert_eq!(
-            expected_rewards,
-            validator_rewards(voter_stake_rewards, vote_accounts)
-        );
-    }
-
-    #[test]
-    fn test_voter_stake_rewards() {
-        let new_stake_delegation = |stake: u64, voter_pubkey: &Pubkey| -> Delegation {
-            Delegation {
-                voter_pubkey: voter_pubkey.clone(),
-                stake,
-                ..Delegation::default()
-            }
-        };
-
-        let voter_pubkey1 = Pubkey::new_rand();
-        let voter_pubkey2 = Pubkey::new_rand();
-
-        let mut stake_accounts = HashMap::new();
-        stake_accounts.insert(
-            Pubkey::new_rand(),
-            new_stake_delegation(100, &voter_pubkey1),
-        );
-        stake_accounts.insert(
-            Pubkey::new_rand(),
-            new_stake_delegation(100, &voter_pubkey2),
-        );
-        stake_accounts.insert(
-            Pubkey::new_rand(),
-            new_stake_delegation(100, &voter_pubkey2),
-        );
-
-        let expected = {
-            let mut map = HashMap::new();
-            map.insert(voter_pubkey1, 100);
-            map.insert(voter_pubkey2, 200);
-            map
-        };
-
-        assert_eq!(expected, voter_stake_rewards(stake_accounts));
+        assert_eq!(expected_rewards, validator_rewards(&vote_accounts));
     }
 
     #[test]
@@ -282,7 +342,8 @@ mod tests {
         results.extend(expected_low_bucket.iter());
         results.extend(expected_bottom_bucket.iter());
 
-        let bucket_winners = bucket_winners(&results);
+        let bucket_winners =
+            bucket_winners(&normalize_winners(&results), &DEFAULT_BUCKET_PERCENTILES);
 
         assert_eq!(
             bucket_winners[0].1,
@@ -299,6 +360,35 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_bucket_winners_names() {
+        let results: Vec<_> = (0..10)
+            .map(|rank| (Pubkey::new_rand(), 10_000 - rank))
+            .collect();
+
+        let bucket_winners = bucket_winners(&normalize_winners(&results), &[10, 30, 60]);
+        let names: Vec<_> = bucket_winners
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        assert_eq!(
+            names,
+            vec!["Top 10%", "10% to 30%", "30% to 60%", "Bottom 40%"]
+        );
+        let sizes: Vec<_> = bucket_winners
+            .iter()
+            .map(|(_, bucket)| bucket.len())
+            .collect();
+        assert_eq!(sizes, vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_validate_bucket_percentiles() {
+        assert!(validate_bucket_percentiles(&DEFAULT_BUCKET_PERCENTILES).is_ok());
+        assert!(validate_bucket_percentiles(&[50, 25, 90]).is_err());
+        assert!(validate_bucket_percentiles(&[25, 50, 110]).is_err());
+    }
+
     #[test]
     fn test_bucket_winners_with_ties() {
         let mut results = Vec::new();
@@ -326,7 +416,8 @@ mod tests {
         results.extend(expected_low_bucket.iter());
         results.extend(expected_bottom_bucket.iter());
 
-        let bucket_winners = bucket_winners(&results);
+        let bucket_winners =
+            bucket_winners(&normalize_winners(&results), &DEFAULT_BUCKET_PERCENTILES);
 
         assert_eq!(
             bucket_winners[0].1,
@@ -342,4 +433,21 @@ mod tests {
             normalize_winners(&expected_bottom_bucket)
         );
     }
+
+    #[test]
+    fn test_evaluate_without_validators() {
+        let accounts = AccountsState::default();
+        let excluded_set = HashSet::new();
+        let context = ScoringContext {
+            accounts: &accounts,
+            leader_slots: &LeaderSlots::default(),
+            baseline_validator: &Pubkey::new_rand(),
+            excluded_set: &excluded_set,
+            first_slot: 0,
+        };
+        match RewardsEarned::new(0, Parameters::default()).evaluate(&context) {
+            Err(WinnerToolError::NoResults(category)) => assert_eq!(category, CATEGORY.name),
+            _ => panic!("expected a no results error"),
+        }
+    }
 }
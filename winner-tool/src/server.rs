@@ -0,0 +1,311 @@
+//! Minimal HTTP server for the latest standings of a watched ledger, meant to run behind a
+//! reverse proxy. `/` serves the HTML leaderboard, `/api/standings` the full JSON report,
+//! `/api/categories/NAME` the report of one category and `/api/validators/KEY` the standing of
+//! one validator, looked up by identity or keybase username. `/metrics` serves the metrics of the
+//! run to Prometheus.
+
+use crate::metrics::{self, Metrics};
+use crate::page;
+use crate::report::Report;
+use crate::session::ScoringResults;
+use log::*;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, RwLock,
+};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Connections served at once, beyond which new connections are closed right away
+const MAX_CONNECTIONS: usize = 64;
+
+/// Standings of the latest scoring, rendered once for every request until the next scoring
+pub struct Leaderboard {
+    report: Report,
+    html: String,
+}
+
+impl Leaderboard {
+    pub fn new(results: &ScoringResults, pubkey_map: &HashMap<String, String>) -> Self {
+        Self {
+            report: Report::new(results, pubkey_map),
+            html: page::render_html(results, pubkey_map),
+        }
+    }
+}
+
+/// Leaderboard shared between the scoring loop and the server, `None` until the first scoring
+pub type SharedLeaderboard = Arc<RwLock<Option<Leaderboard>>>;
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    fn json<T: Serialize>(value: &T) -> Self {
+        Self {
+            status: "200 OK",
+            content_type: "application/json",
+            body: serde_json::to_string_pretty(value).unwrap(),
+        }
+    }
+
+    fn text(status: &'static str, body: &str) -> Self {
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body: format!("{}\n", body),
+        }
+    }
+}
+
+fn respond(
+    leaderboard: &Option<Leaderboard>,
+    metrics: &Metrics,
+    method: &str,
+    target: &str,
+) -> Response {
+    if method != "GET" && method != "HEAD" {
+        return Response::text("405 Method Not Allowed", "Only GET requests are served");
+    }
+    let path = target.split('?').next().unwrap_or_default();
+    if path == "/metrics" {
+        return Response {
+            status: "200 OK",
+            content_type: metrics::CONTENT_TYPE,
+            body: metrics.render(),
+        };
+    }
+    let leaderboard = match leaderboard {
+        Some(leaderboard) => leaderboard,
+        None => {
+            return Response::text(
+                "503 Service Unavailable",
+                "The ledger has not been scored yet",
+            )
+        }
+    };
+
+    let segments: Vec<&str> = path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect();
+    match segments.as_slice() {
+        [] => Response {
+            status: "200 OK",
+            content_type: "text/html; charset=utf-8",
+            body: leaderboard.html.clone(),
+        },
+        ["api", "standings"] => Response::json(&leaderboard.report),
+        ["api", "categories", name] => leaderboard
+            .report
+            .categories
+            .iter()
+            .find(|category| category.category == *name)
+            .map(Response::json)
+            .unwrap_or_else(|| Response::text("404 Not Found", "Unknown category")),
+        ["api", "validators", key] => leaderboard
+            .report
+            .validator_standing(key)
+            .map(|standing| Response::json(&standing))
+            .unwrap_or_else(|| Response::text("404 Not Found", "Unknown validator")),
+        _ => Response::text("404 Not Found", "Not found"),
+    }
+}
+
+fn handle_connection(
+    stream: TcpStream,
+    leaderboard: &SharedLeaderboard,
+    metrics: &Metrics,
+) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // The headers are read but not needed
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim_end().is_empty() {
+            break;
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let target = parts.next().unwrap_or_default();
+    let response = respond(&leaderboard.read().unwrap(), metrics, method, target);
+
+    let mut stream = reader.into_inner();
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.content_type,
+        response.body.len()
+    )?;
+    if method != "HEAD" {
+        stream.write_all(response.body.as_bytes())?;
+    }
+    stream.flush()
+}
+
+/// Serves `leaderboard` and `metrics` to the connections of `listener` on a separate thread.
+/// Every connection is handled on a thread of its own, so that a slow client does not hold up
+/// the others.
+pub fn serve(
+    listener: TcpListener,
+    leaderboard: SharedLeaderboard,
+    metrics: Arc<Metrics>,
+) -> JoinHandle<()> {
+    thread::Builder::new()
+        .name("tds-server".to_string())
+        .spawn(move || {
+            let connections = Arc::new(AtomicUsize::new(0));
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        warn!("Failed to accept a connection: {}", err);
+                        continue;
+                    }
+                };
+                if connections.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+                    connections.fetch_sub(1, Ordering::SeqCst);
+                    warn!("Closed a connection, {} are already open", MAX_CONNECTIONS);
+                    continue;
+                }
+                let leaderboard = leaderboard.clone();
+                let metrics = metrics.clone();
+                let connections = connections.clone();
+                let spawned = thread::Builder::new()
+                    .name("tds-connection".to_string())
+                    .spawn(move || {
+                        if let Err(err) = handle_connection(stream, &leaderboard, &metrics) {
+                            warn!("Failed to serve a request: {}", err);
+                        }
+                        connections.fetch_sub(1, Ordering::SeqCst);
+                    });
+                if let Err(err) = spawned {
+                    warn!("Failed to start a connection thread: {}", err);
+                }
+            }
+        })
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::ScoringParameters;
+    use crate::winner::{Score, Winners};
+    use crate::{confirmation_latency, progress::PhaseTimings};
+    use solana_sdk::pubkey::Pubkey;
+    use std::io::Read;
+
+    fn leaderboard(validator: Pubkey) -> Leaderboard {
+        let results = ScoringResults {
+            first_slot: 0,
+            last_slot: 100,
+            stakes: vec![(validator, 42)],
+            winners: vec![Winners {
+                category: confirmation_latency::CATEGORY,
+                baseline: Some(Score::Points(1)),
+                results: vec![(validator, Score::Points(3))],
+                top_winners: vec![(validator, Score::Points(3))],
+                bucket_winners: vec![],
+                details: HashMap::new(),
+                epochs: vec![],
+            }],
+            parameters: ScoringParameters::default(),
+            timings: PhaseTimings::default(),
+        };
+        let mut pubkey_map = HashMap::new();
+        pubkey_map.insert(validator.to_string(), "alice".to_string());
+        Leaderboard::new(&results, &pubkey_map)
+    }
+
+    #[test]
+    fn test_respond() {
+        let validator = Pubkey::new_rand();
+        let metrics = Metrics::default();
+        let response = respond(&None, &metrics, "GET", "/metrics");
+        assert_eq!(response.content_type, metrics::CONTENT_TYPE);
+        assert!(response.body.contains("tds_replay_slot 0"));
+        assert_eq!(
+            respond(&None, &metrics, "GET", "/").status,
+            "503 Service Unavailable"
+        );
+
+        let leaderboard = Some(leaderboard(validator));
+        let response = respond(&leaderboard, &metrics, "GET", "/");
+        assert_eq!(response.content_type, "text/html; charset=utf-8");
+        assert!(response.body.contains("alice"));
+
+        let response = respond(
+            &leaderboard,
+            &metrics,
+            "GET",
+            "/api/categories/latency?pretty",
+        );
+        assert_eq!(response.status, "200 OK");
+        assert!(response
+            .body
+            .contains("\"title\": \"Confirmation Latency\""));
+
+        let response = respond(&leaderboard, &metrics, "GET", "/api/validators/alice");
+        assert_eq!(response.status, "200 OK");
+        assert!(response.body.contains(&validator.to_string()));
+        assert_eq!(
+            respond(
+                &leaderboard,
+                &metrics,
+                "GET",
+                &format!("/api/validators/{}", validator)
+            )
+            .body,
+            response.body
+        );
+
+        assert_eq!(
+            respond(&leaderboard, &metrics, "GET", "/api/categories/unknown").status,
+            "404 Not Found"
+        );
+        assert_eq!(
+            respond(&leaderboard, &metrics, "POST", "/api/standings").status,
+            "405 Method Not Allowed"
+        );
+    }
+
+    #[test]
+    fn test_serve() {
+        let validator = Pubkey::new_rand();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let _server = serve(
+            listener,
+            Arc::new(RwLock::new(Some(leaderboard(validator)))),
+            Arc::new(Metrics::default()),
+        );
+
+        // An idle client does not hold up the others
+        let _idle_stream = TcpStream::connect(addr).unwrap();
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.set_read_timeout(Some(READ_TIMEOUT / 2)).unwrap();
+        stream
+            .write_all(b"GET /api/standings HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("Content-Type: application/json"));
+        assert!(response.contains("\"last_slot\": 100"));
+    }
+}
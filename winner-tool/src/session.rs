@@ -0,0 +1,1084 @@
+//! Replays a Tour de SOL ledger, or reads back the events of a replay from an event log, and
+//! evaluates the enabled quantitative categories.
+
+use crate::bank_hash::BankHashCheck;
+use crate::category::{CategoryRegistry, ScoringCategory, ScoringContext};
+use crate::checkpoint::{self, CheckpointState};
+use crate::error::WinnerToolError;
+use crate::event_log::{self, EventLogWriter};
+use crate::events::{self, AccountsState, Event, LedgerSummary, Recorder};
+use crate::metrics::Metrics;
+use crate::progress::{self, PhaseTimings, ReplayProgress};
+use crate::winner::Winners;
+use crate::{availability, confirmation_latency, rewards_earned, utils};
+use log::*;
+use serde::{Deserialize, Serialize};
+use solana_ledger::{
+    bank_forks::BankForks,
+    blockstore::Blockstore,
+    blockstore_processor::{process_blockstore, process_blockstore_from_root, ProcessOptions},
+};
+use solana_runtime::bank::Bank;
+use solana_sdk::{
+    clock::Slot, epoch_schedule::EpochSchedule, genesis_config::GenesisConfig, hash::Hash,
+    pubkey::Pubkey,
+};
+use std::{
+    cmp::{max, min},
+    collections::{BTreeMap, HashSet},
+    fs::{self, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{channel, Sender},
+        Arc, Mutex, RwLock,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+use tempfile::TempDir;
+
+/// Tunable parameters of every category
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScoringParameters {
+    pub availability: availability::Parameters,
+    pub latency: confirmation_latency::Parameters,
+    pub rewards_earned: rewards_earned::Parameters,
+}
+
+impl ScoringParameters {
+    pub fn validate(&self) -> Result<(), WinnerToolError> {
+        utils::validate_baseline_buckets(&self.availability.baseline_buckets)?;
+        utils::validate_baseline_buckets(&self.latency.baseline_buckets)?;
+        rewards_earned::validate_bucket_percentiles(&self.rewards_earned.bucket_percentiles)
+    }
+
+    /// Renders the parameters in the syntax of the `parameters` section of a config file
+    pub fn to_yaml(&self) -> String {
+        let yaml = serde_yaml::to_string(self).unwrap();
+        yaml.trim_start_matches("---\n").to_string()
+    }
+}
+
+/// Parameters of a scoring run
+#[derive(Clone)]
+pub struct ScoringOptions {
+    /// Identity of the Solana validator that availability and latency are measured against,
+    /// which scoring requires
+    pub baseline_validator: Option<Pubkey>,
+    /// Identities that are left out of the results
+    pub excluded_set: HashSet<Pubkey>,
+    /// Lamports every validator started TdS with
+    pub starting_balance: u64,
+    /// Score the window of the ledger that starts at this slot, instead of starting at genesis
+    pub start_slot: Option<Slot>,
+    /// Stop replaying the ledger at this slot
+    pub final_slot: Option<Slot>,
+    pub parameters: ScoringParameters,
+    /// Save checkpoints into this directory while replaying
+    pub checkpoint_path: Option<PathBuf>,
+    /// Minimum number of slots between checkpoints
+    pub checkpoint_interval: Slot,
+    /// Resume replay from this checkpoint, or from the latest checkpoint in this directory
+    pub resume_from: Option<PathBuf>,
+    /// Start replay from the bank in this snapshot archive instead of genesis. The window then
+    /// starts no earlier than the snapshot.
+    pub snapshot_archive: Option<PathBuf>,
+    /// Number of threads that replay transactions, one per core when not set. Scores do not
+    /// depend on it.
+    pub replay_threads: Option<usize>,
+    /// Verify the proof of history of every replayed slot
+    pub verify_poh: bool,
+    /// Trusted hashes of the banks at these slots, which replay fails if it does not reproduce
+    pub expected_bank_hashes: BTreeMap<Slot, Hash>,
+    /// Metrics that replay reports its progress to
+    pub metrics: Option<Arc<Metrics>>,
+}
+
+impl ScoringOptions {
+    /// Baseline validator, failing if it is not set
+    pub fn require_baseline_validator(&self) -> Result<&Pubkey, WinnerToolError> {
+        self.baseline_validator.as_ref().ok_or_else(|| {
+            WinnerToolError::InvalidArgument(
+                "--baseline-validator is not set on the command line or in --config".to_string(),
+            )
+        })
+    }
+}
+
+/// Outcome of a scoring run
+pub struct ScoringResults {
+    /// First slot of the scored window
+    pub first_slot: Slot,
+    /// Slot of the final bank
+    pub last_slot: Slot,
+    /// Stake of every validator identity that was not excluded
+    pub stakes: Vec<(Pubkey, u64)>,
+    /// Winners of every category
+    pub winners: Vec<Winners>,
+    /// Parameters the categories were scored with
+    pub parameters: ScoringParameters,
+    /// Time spent replaying or reading the ledger and computing the winners of each category
+    pub timings: PhaseTimings,
+}
+
+pub struct ScoringSession<'a> {
+    genesis_config: &'a GenesisConfig,
+    blockstore: &'a Blockstore,
+    options: ScoringOptions,
+    categories: Arc<RwLock<Vec<Box<dyn ScoringCategory>>>>,
+}
+
+impl<'a> ScoringSession<'a> {
+    /// Session that scores every built-in category
+    pub fn new(
+        genesis_config: &'a GenesisConfig,
+        blockstore: &'a Blockstore,
+        options: ScoringOptions,
+    ) -> Self {
+        let categories = CategoryRegistry::default().create_all(&options);
+        Self::with_categories(genesis_config, blockstore, options, categories)
+    }
+
+    /// Session that scores `categories`, reported in the order given
+    pub fn with_categories(
+        genesis_config: &'a GenesisConfig,
+        blockstore: &'a Blockstore,
+        options: ScoringOptions,
+        categories: Vec<Box<dyn ScoringCategory>>,
+    ) -> Self {
+        Self {
+            genesis_config,
+            blockstore,
+            options,
+            categories: Arc::new(RwLock::new(categories)),
+        }
+    }
+
+    // Restores the category states of the checkpoint in `dir` and returns its bank, whose accounts
+    // are kept in `work_path`, along with the slot of the snapshot archive the checkpointed run was
+    // bootstrapped from
+    fn restore_checkpoint(
+        &self,
+        dir: &Path,
+        work_path: &Path,
+        recorder: &mut Recorder,
+    ) -> Result<(Bank, Option<Slot>), WinnerToolError> {
+        let (bank, state) = checkpoint::load(dir, work_path)?;
+        if state.start_slot != self.options.start_slot {
+            return Err(WinnerToolError::Checkpoint(format!(
+                "Checkpoint {} was recorded for a window starting at {:?} instead of {:?}",
+                dir.display(),
+                state.start_slot,
+                self.options.start_slot
+            )));
+        }
+
+        let mut categories = self.categories.write().unwrap();
+        let recorded_names: Vec<&str> = state
+            .categories
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        let names: Vec<&str> = categories
+            .iter()
+            .map(|category| category.category().name)
+            .collect();
+        if recorded_names != names {
+            return Err(WinnerToolError::Checkpoint(format!(
+                "Checkpoint {} was recorded for the categories {} instead of {}",
+                dir.display(),
+                recorded_names.join(","),
+                names.join(",")
+            )));
+        }
+        for (category, (_, category_state)) in categories.iter_mut().zip(&state.categories) {
+            category.restore_state(category_state)?;
+        }
+        recorder.resume(state.window_started);
+
+        info!(
+            "Resuming replay from the checkpoint at slot {}",
+            bank.slot()
+        );
+        Ok((bank, state.snapshot_slot))
+    }
+
+    fn save_checkpoint(
+        &self,
+        checkpoint_path: &Path,
+        bank_forks: &mut BankForks,
+        slot: Slot,
+        snapshot_slot: Option<Slot>,
+        window_started: bool,
+    ) -> Result<(), WinnerToolError> {
+        let categories = self
+            .categories
+            .read()
+            .unwrap()
+            .iter()
+            .map(|category| Ok((category.category().name.to_string(), category.save_state()?)))
+            .collect::<Result<_, WinnerToolError>>()?;
+        let state = CheckpointState {
+            slot,
+            start_slot: self.options.start_slot,
+            snapshot_slot,
+            window_started,
+            categories,
+        };
+        let dir = checkpoint::save(checkpoint_path, bank_forks, &state)?;
+        info!("Saved a checkpoint at slot {} to {}", slot, dir.display());
+        Ok(())
+    }
+
+    // Starts a replay from the checkpoint or snapshot archive of the options, or from genesis,
+    // letting every category observe its events and appending them to `event_log` if given
+    fn start_replay(&self, event_log: Option<EventLogWriter>) -> Result<Replay, WinnerToolError> {
+        let recorder = Arc::new(Mutex::new(Recorder::new(self.options.start_slot)));
+        let (event_sender, observer) = spawn_observer(self.categories.clone(), event_log);
+        let mut replay = Replay {
+            recorder,
+            event_sender,
+            observer,
+            bank_hashes: Arc::new(Mutex::new(BankHashCheck::new(
+                self.options.expected_bank_hashes.clone(),
+            ))),
+            root_bank: None,
+            snapshot_slot: None,
+            _snapshot_work_dir: None,
+        };
+
+        match (&self.options.resume_from, &self.options.snapshot_archive) {
+            (Some(path), _) => {
+                let dir = checkpoint::find(path)?;
+                let work_dir = snapshot_work_dir()?;
+                let (bank, checkpoint_snapshot_slot) = self.restore_checkpoint(
+                    &dir,
+                    work_dir.path(),
+                    &mut replay.recorder.lock().unwrap(),
+                )?;
+                replay.snapshot_slot = checkpoint_snapshot_slot;
+                replay.root_bank = Some(Arc::new(bank));
+                replay._snapshot_work_dir = Some(work_dir);
+            }
+            (None, Some(archive)) => {
+                let work_dir = snapshot_work_dir()?;
+                let bank = checkpoint::bank_from_archive(archive, work_dir.path())?;
+                info!(
+                    "Starting replay from the snapshot of slot {} in {}",
+                    bank.slot(),
+                    archive.display()
+                );
+                // Categories cannot observe the ledger before the snapshot, so the window starts
+                // at the snapshot unless it starts later
+                if self.options.start_slot.unwrap_or(0) <= bank.slot() {
+                    let event = replay.recorder.lock().unwrap().start_window(&bank);
+                    replay.event_sender.send(vec![event]);
+                }
+                replay.snapshot_slot = Some(bank.slot());
+                replay.root_bank = Some(Arc::new(bank));
+                replay._snapshot_work_dir = Some(work_dir);
+            }
+            (None, None) => (),
+        }
+        if let Some(bank) = &replay.root_bank {
+            replay
+                .bank_hashes
+                .lock()
+                .unwrap()
+                .observe(bank.slot(), bank.hash());
+        }
+        Ok(replay)
+    }
+
+    // Continues `replay` up to `halt_slot`, or to the end of the ledger, from where it can be
+    // continued again from the last root. Returns the state of the ledger once replay has halted.
+    fn replay_to(
+        &self,
+        replay: &mut Replay,
+        halt_slot: Option<Slot>,
+    ) -> Result<LedgerSummary, WinnerToolError> {
+        // Turn the bank after each batch of entries into events for the categories. However many
+        // threads replay transactions, the callback runs on the thread that replays the slot,
+        // after every batch and in ledger order, so the events do not depend on the thread count.
+        let last_slot = halt_slot.or_else(|| progress::last_blockstore_slot(self.blockstore));
+        let progress = Mutex::new(ReplayProgress::new(last_slot));
+        let entry_callback = {
+            let recorder = replay.recorder.clone();
+            let event_sender = replay.event_sender.clone();
+            let bank_hashes = replay.bank_hashes.clone();
+            let metrics = self.options.metrics.clone();
+            Arc::new(move |bank: &Bank| {
+                progress.lock().unwrap().observe(bank.slot());
+                if let Some(metrics) = &metrics {
+                    metrics.observe_entry_batch(bank.slot(), bank.tick_height());
+                }
+                // The parent of a bank is frozen before any of its entries are replayed
+                if bank.slot() > bank.parent_slot() {
+                    bank_hashes
+                        .lock()
+                        .unwrap()
+                        .observe(bank.parent_slot(), bank.parent_hash());
+                }
+                let events = recorder.lock().unwrap().observe(bank);
+                event_sender.send(events);
+            })
+        };
+
+        // Replay in segments that end at checkpoint slots, resuming each segment from the
+        // checkpointed bank
+        let (bank_forks, leader_schedule_cache) = loop {
+            let checkpoint_slot = self.options.checkpoint_path.as_ref().and_then(|_| {
+                let last_slot = replay
+                    .root_bank
+                    .as_ref()
+                    .map(|bank| bank.slot())
+                    .unwrap_or(0);
+                checkpoint::next_checkpoint_slot(
+                    self.blockstore,
+                    last_slot,
+                    self.options.checkpoint_interval,
+                    halt_slot,
+                )
+            });
+            let opts = ProcessOptions {
+                poh_verify: self.options.verify_poh,
+                dev_halt_at_slot: checkpoint_slot.or(halt_slot),
+                full_leader_cache: true,
+                entry_callback: Some(entry_callback.clone()),
+                override_num_threads: self.options.replay_threads,
+                ..ProcessOptions::default()
+            };
+
+            let (mut bank_forks, _bank_forks_info, leader_schedule_cache) = match replay
+                .root_bank
+                .take()
+            {
+                Some(bank) => {
+                    process_blockstore_from_root(self.genesis_config, self.blockstore, bank, &opts)
+                }
+                None => process_blockstore(self.genesis_config, self.blockstore, vec![], opts),
+            }
+            .map_err(|err| {
+                WinnerToolError::Ledger(format!("Failed to process ledger: {:?}", err))
+            })?;
+            replay.check_bank_hashes(&bank_forks)?;
+
+            match (checkpoint_slot, &self.options.checkpoint_path) {
+                (Some(slot), Some(checkpoint_path)) => {
+                    replay.event_sender.flush()?;
+                    let window_started = replay.recorder.lock().unwrap().window_started();
+                    self.save_checkpoint(
+                        checkpoint_path,
+                        &mut bank_forks,
+                        slot,
+                        replay.snapshot_slot,
+                        window_started,
+                    )?;
+                    // Replaying the next segment from genesis would let the categories observe
+                    // every event again
+                    let bank = bank_forks.get(slot).cloned().ok_or_else(|| {
+                        WinnerToolError::Ledger(format!(
+                            "Replay halted at checkpoint slot {} without a bank for it",
+                            slot
+                        ))
+                    })?;
+                    replay.root_bank = Some(bank);
+                }
+                _ => break (bank_forks, leader_schedule_cache),
+            }
+        };
+
+        let bank = bank_forks.working_bank();
+        replay.root_bank = bank_forks.get(bank_forks.root()).cloned();
+        let first_slot = max(
+            self.options.start_slot.unwrap_or(0),
+            replay.snapshot_slot.unwrap_or(0),
+        );
+        Ok(LedgerSummary {
+            first_slot,
+            accounts: AccountsState::new(&bank),
+            leader_slots: events::leader_slots(
+                &bank,
+                self.blockstore,
+                &leader_schedule_cache,
+                first_slot,
+            )?,
+        })
+    }
+
+    // Replays the ledger, letting every category observe its events and appending them to
+    // `event_log` if given. Returns the state of the ledger once replay has finished.
+    fn replay(&self, event_log: Option<EventLogWriter>) -> Result<LedgerSummary, WinnerToolError> {
+        let mut replay = self.start_replay(event_log)?;
+        let summary = self.replay_to(&mut replay, self.options.final_slot)?;
+        replay.bank_hashes.lock().unwrap().check_all()?;
+        replay.finish(&summary)?;
+        Ok(summary)
+    }
+
+    /// Replays the ledger and computes the winners of every category
+    pub fn run(&self) -> Result<ScoringResults, WinnerToolError> {
+        self.options.require_baseline_validator()?;
+        let mut timings = PhaseTimings::default();
+        let summary = timings.time("replay", || self.replay(None))?;
+        let categories = self.categories.read().unwrap();
+        score(&categories, &self.options, &summary, timings)
+    }
+
+    /// Replays the ledger and records its events to an event log at `path`, from which the
+    /// categories can be scored with `score_event_log` without replaying the ledger again
+    pub fn record(&self, path: &Path) -> Result<(), WinnerToolError> {
+        if self.options.resume_from.is_some() {
+            return Err(WinnerToolError::InvalidArgument(
+                "An event log cannot be recorded when resuming from a checkpoint".to_string(),
+            ));
+        }
+        let event_log = EventLogWriter::create(path)?;
+        let summary = self.replay(Some(event_log))?;
+        info!(
+            "Recorded slots {} to {} to {}",
+            summary.first_slot,
+            summary.accounts.slot,
+            path.display()
+        );
+        Ok(())
+    }
+}
+
+// Temporary directory for the accounts of a bank restored from a snapshot archive or checkpoint
+fn snapshot_work_dir() -> Result<TempDir, WinnerToolError> {
+    tempfile::tempdir().map_err(|err| {
+        WinnerToolError::Checkpoint(format!("Unable to create a snapshot directory: {}", err))
+    })
+}
+
+// Replay that can continue from its root bank as the ledger grows
+struct Replay {
+    recorder: Arc<Mutex<Recorder>>,
+    event_sender: EventSender,
+    observer: JoinHandle<Option<EventLogWriter>>,
+    // Hashes of the replayed banks that have an expected hash
+    bank_hashes: Arc<Mutex<BankHashCheck>>,
+    root_bank: Option<Arc<Bank>>,
+    // Slot of the snapshot archive replay was bootstrapped from
+    snapshot_slot: Option<Slot>,
+    // Holds the accounts of a bank bootstrapped from a snapshot archive or checkpoint for the
+    // whole run
+    _snapshot_work_dir: Option<TempDir>,
+}
+
+impl Replay {
+    // Checks the banks replayed so far against their expected hashes, including the frozen banks
+    // that replay halted at, whose children have not been replayed yet
+    fn check_bank_hashes(&self, bank_forks: &BankForks) -> Result<(), WinnerToolError> {
+        let mut bank_hashes = self.bank_hashes.lock().unwrap();
+        let frozen_banks: Vec<Arc<Bank>> = bank_hashes
+            .slots()
+            .filter_map(|slot| bank_forks.get(*slot).cloned())
+            .filter(|bank| bank.is_frozen())
+            .collect();
+        for bank in frozen_banks {
+            bank_hashes.observe(bank.slot(), bank.hash());
+        }
+        bank_hashes.check()
+    }
+
+    // Waits for the categories to observe every event and completes the event log
+    fn finish(self, summary: &LedgerSummary) -> Result<(), WinnerToolError> {
+        self.event_sender.finish();
+        let event_log = self
+            .observer
+            .join()
+            .map_err(|_| WinnerToolError::ObserverFailed)?;
+        if let Some(event_log) = event_log {
+            event_log.finish(summary)?;
+        }
+        Ok(())
+    }
+}
+
+enum ObserverMessage {
+    Events(Vec<Event>),
+    /// Replies once every event sent before has been observed
+    Flush(Sender<()>),
+    Finish,
+}
+
+// Sends the events of replay to the observer thread
+#[derive(Clone)]
+struct EventSender(Arc<Mutex<Sender<ObserverMessage>>>);
+
+impl EventSender {
+    fn send(&self, events: Vec<Event>) {
+        if !events.is_empty() {
+            // The observer thread only stops early if a category panicked, which is reported
+            // when the thread is joined
+            let _ = self.0.lock().unwrap().send(ObserverMessage::Events(events));
+        }
+    }
+
+    // Waits until the categories have observed every event sent so far
+    fn flush(&self) -> Result<(), WinnerToolError> {
+        let (done_sender, done_receiver) = channel();
+        let _ = self
+            .0
+            .lock()
+            .unwrap()
+            .send(ObserverMessage::Flush(done_sender));
+        done_receiver
+            .recv()
+            .map_err(|_| WinnerToolError::ObserverFailed)
+    }
+
+    fn finish(&self) {
+        let _ = self.0.lock().unwrap().send(ObserverMessage::Finish);
+    }
+}
+
+// Lets the categories observe the events of replay on a separate thread, in the order they were
+// sent, so that replay does not wait for the categories. The thread returns the event log once
+// every event has been written to it.
+fn spawn_observer(
+    categories: Arc<RwLock<Vec<Box<dyn ScoringCategory>>>>,
+    mut event_log: Option<EventLogWriter>,
+) -> (EventSender, JoinHandle<Option<EventLogWriter>>) {
+    let (sender, receiver) = channel();
+    let observer = thread::Builder::new()
+        .name("tds-observer".to_string())
+        .spawn(move || {
+            for message in receiver {
+                match message {
+                    ObserverMessage::Events(events) => {
+                        let mut categories = categories.write().unwrap();
+                        for event in events {
+                            for category in categories.iter_mut() {
+                                category.observe(&event);
+                            }
+                            if let Some(event_log) = event_log.as_mut() {
+                                event_log.write(&event);
+                            }
+                        }
+                    }
+                    ObserverMessage::Flush(done_sender) => {
+                        let _ = done_sender.send(());
+                    }
+                    ObserverMessage::Finish => break,
+                }
+            }
+            event_log
+        })
+        .unwrap();
+    (EventSender(Arc::new(Mutex::new(sender))), observer)
+}
+
+/// Computes the winners of every category from the event log at `path`
+pub fn score_event_log(
+    path: &Path,
+    options: &ScoringOptions,
+    mut categories: Vec<Box<dyn ScoringCategory>>,
+) -> Result<ScoringResults, WinnerToolError> {
+    options.require_baseline_validator()?;
+    let mut timings = PhaseTimings::default();
+    let summary = timings.time("read event log", || {
+        event_log::read(path, |event| {
+            for category in categories.iter_mut() {
+                category.observe(&event);
+            }
+        })
+    })?;
+    score(&categories, options, &summary, timings)
+}
+
+/// How often a growing ledger is scored
+pub struct WatchOptions {
+    /// Number of slots after which the ledger is scored again. It is also scored at the first
+    /// root of every epoch.
+    pub interval: Slot,
+    /// Time to wait before looking for new roots again
+    pub poll_interval: Duration,
+}
+
+// Number of times in a row a watched ledger may fail to open before watching stops
+const MAX_FAILED_OPENS: u32 = 8;
+
+// Longest time to wait before opening a watched ledger again
+const MAX_OPEN_BACKOFF: Duration = Duration::from_secs(600);
+
+/// Path of the lock file of a watched ledger, `ledger.lock` next to a `ledger` directory
+pub fn watch_lock_path(ledger_path: &Path) -> PathBuf {
+    let ledger_path = fs::canonicalize(ledger_path).unwrap_or_else(|_| ledger_path.to_path_buf());
+    match ledger_path.file_name() {
+        Some(name) => {
+            let mut lock_name = name.to_os_string();
+            lock_name.push(".lock");
+            ledger_path.with_file_name(lock_name)
+        }
+        None => ledger_path.join("watch.lock"),
+    }
+}
+
+// Lock file that watch mode holds while a watched ledger is open, and that a job refreshing the
+// copy of the ledger holds while it replaces it
+struct WatchLock {
+    path: PathBuf,
+}
+
+impl WatchLock {
+    // Creates the lock file at `path`, or returns `None` if someone else holds it
+    fn acquire(path: PathBuf) -> Result<Option<Self>, WinnerToolError> {
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(_) => Ok(Some(Self { path })),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => Ok(None),
+            Err(err) => Err(WinnerToolError::Ledger(format!(
+                "Unable to create the lock file {}: {}",
+                path.display(),
+                err
+            ))),
+        }
+    }
+}
+
+impl Drop for WatchLock {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_file(&self.path) {
+            warn!(
+                "Unable to remove the lock file {}: {}",
+                self.path.display(),
+                err
+            );
+        }
+    }
+}
+
+/// Blockstore of a watched ledger, which is kept open no longer than a pass replays it.
+///
+/// The blockstore of this release of Solana can only be opened by one process at a time and has
+/// no secondary read-only mode, so the ledger of a running validator cannot be watched. Instead,
+/// watch mode watches a copy of it that a separate job refreshes. The lock file at
+/// `watch_lock_path` tells the job when the copy is open: watch mode creates it before opening
+/// the copy and removes it once the copy is closed, and waits while the job holds it.
+pub struct WatchedLedger {
+    pub blockstore: Blockstore,
+    // Fields are dropped in order, so the lock is released once the blockstore is closed
+    _lock: WatchLock,
+}
+
+impl WatchedLedger {
+    // Opens the ledger at `ledger_path`, or returns `None` while the copy is being refreshed
+    fn try_open(ledger_path: &Path) -> Result<Option<Self>, WinnerToolError> {
+        let lock = match WatchLock::acquire(watch_lock_path(ledger_path))? {
+            Some(lock) => lock,
+            None => return Ok(None),
+        };
+        let blockstore = Blockstore::open(ledger_path).map_err(|err| {
+            WinnerToolError::Ledger(format!(
+                "Failed to open ledger at {}: {:?}. The ledger of a running validator cannot be \
+                 watched with Solana v0.22.4, which lets only one process open it; watch a copy of \
+                 the ledger that is refreshed between passes instead",
+                ledger_path.display(),
+                err
+            ))
+        })?;
+        Ok(Some(Self {
+            blockstore,
+            _lock: lock,
+        }))
+    }
+
+    /// Opens the ledger at `ledger_path`, waiting while the copy is being refreshed
+    pub fn open(ledger_path: &Path, poll_interval: Duration) -> Result<Self, WinnerToolError> {
+        open_with_retries(
+            || Self::try_open(ledger_path),
+            false,
+            poll_interval,
+            thread::sleep,
+        )
+    }
+}
+
+// Time to wait before opening a watched ledger again after `failed_opens` failures in a row
+fn open_backoff(poll_interval: Duration, failed_opens: u32) -> Duration {
+    min(
+        poll_interval * 2u32.pow(min(failed_opens, 16)),
+        MAX_OPEN_BACKOFF,
+    )
+}
+
+// Opens a watched ledger with `open`, which returns `None` while the ledger is locked. Waits
+// `poll_interval` while it is locked, and `open_backoff` after every failure. Fails at the first
+// failure unless `retry` is set, and after `MAX_FAILED_OPENS` failures in a row otherwise.
+fn open_with_retries<T, O, S>(
+    mut open: O,
+    retry: bool,
+    poll_interval: Duration,
+    mut sleep: S,
+) -> Result<T, WinnerToolError>
+where
+    O: FnMut() -> Result<Option<T>, WinnerToolError>,
+    S: FnMut(Duration),
+{
+    let mut failed_opens = 0;
+    loop {
+        match open() {
+            Ok(Some(ledger)) => return Ok(ledger),
+            Ok(None) => {
+                info!("Waiting for the watched ledger to be refreshed");
+                sleep(poll_interval);
+            }
+            Err(err) => {
+                failed_opens += 1;
+                if !retry || failed_opens >= MAX_FAILED_OPENS {
+                    return Err(err);
+                }
+                let backoff = open_backoff(poll_interval, failed_opens);
+                warn!(
+                    "{}; retrying in {}s ({} of {} attempts)",
+                    err,
+                    backoff.as_secs(),
+                    failed_opens,
+                    MAX_FAILED_OPENS
+                );
+                sleep(backoff);
+            }
+        }
+    }
+}
+
+// Rooted slot that a ledger replayed up to `last_slot` is scored at next, if it has been rooted
+fn next_watch_slot(
+    blockstore: &Blockstore,
+    epoch_schedule: &EpochSchedule,
+    last_slot: Slot,
+    interval: Slot,
+) -> Option<Slot> {
+    let next_epoch = epoch_schedule.get_epoch(last_slot) + 1;
+    let next_epoch_slot = epoch_schedule.get_first_slot_in_epoch(next_epoch);
+    let interval = min(interval, next_epoch_slot - last_slot);
+    checkpoint::next_checkpoint_slot(blockstore, last_slot, interval, None)
+}
+
+/// Scores a ledger that is still growing, passing the results to `emit` every time replay reaches
+/// a new root `interval` slots after the last scoring or in a new epoch. The categories keep
+/// their state between passes, so each pass only replays the slots rooted since the last one.
+/// The ledger is opened as a `WatchedLedger` and closed before each pass is scored. Runs until
+/// an error occurs. Fails at once if the ledger cannot be opened at the start, and after
+/// `MAX_FAILED_OPENS` failures in a row later on, waiting longer after each failure.
+pub fn watch_ledger<F>(
+    genesis_config: &GenesisConfig,
+    ledger_path: &Path,
+    options: ScoringOptions,
+    categories: Vec<Box<dyn ScoringCategory>>,
+    watch_options: &WatchOptions,
+    mut emit: F,
+) -> Result<(), WinnerToolError>
+where
+    F: FnMut(ScoringResults),
+{
+    options.require_baseline_validator()?;
+    if options.final_slot.is_some() {
+        return Err(WinnerToolError::InvalidArgument(
+            "A watched ledger cannot be scored up to a final slot".to_string(),
+        ));
+    }
+    let categories = Arc::new(RwLock::new(categories));
+    let mut replay = None;
+    loop {
+        // A ledger that cannot be opened at the start is most likely held by a validator
+        let ledger = open_with_retries(
+            || WatchedLedger::try_open(ledger_path),
+            replay.is_some(),
+            watch_options.poll_interval,
+            thread::sleep,
+        )?;
+        let session = ScoringSession {
+            genesis_config,
+            blockstore: &ledger.blockstore,
+            options: options.clone(),
+            categories: categories.clone(),
+        };
+        if replay.is_none() {
+            replay = Some(session.start_replay(None)?);
+        }
+        let active_replay = replay.as_mut().unwrap();
+
+        let last_slot = active_replay
+            .root_bank
+            .as_ref()
+            .map(|bank| bank.slot())
+            .unwrap_or(0);
+        let next_slot = next_watch_slot(
+            &ledger.blockstore,
+            &genesis_config.epoch_schedule,
+            last_slot,
+            watch_options.interval,
+        );
+        let slot = match next_slot {
+            Some(slot) => slot,
+            None => {
+                drop(session);
+                drop(ledger);
+                thread::sleep(watch_options.poll_interval);
+                continue;
+            }
+        };
+
+        let mut timings = PhaseTimings::default();
+        let summary = timings.time("replay", || session.replay_to(active_replay, Some(slot)))?;
+        active_replay.event_sender.flush()?;
+        // The categories have observed the pass, so the copy can be refreshed while it is scored
+        drop(session);
+        drop(ledger);
+        let categories = categories.read().unwrap();
+        emit(score(&categories, &options, &summary, timings)?);
+    }
+}
+
+// Evaluates every category once the ledger has been observed
+fn score(
+    categories: &[Box<dyn ScoringCategory>],
+    options: &ScoringOptions,
+    summary: &LedgerSummary,
+    mut timings: PhaseTimings,
+) -> Result<ScoringResults, WinnerToolError> {
+    let ScoringOptions {
+        excluded_set,
+        parameters,
+        ..
+    } = options;
+    let baseline_validator = options.require_baseline_validator()?;
+
+    let mut stakes = vec![];
+    for vote_account in summary.accounts.vote_accounts.values() {
+        if !excluded_set.contains(&vote_account.node_pubkey) {
+            stakes.push((vote_account.node_pubkey, vote_account.stake));
+        }
+    }
+
+    let context = ScoringContext {
+        accounts: &summary.accounts,
+        leader_slots: &summary.leader_slots,
+        baseline_validator,
+        excluded_set,
+        first_slot: summary.first_slot,
+    };
+    let winners = categories
+        .iter()
+        .map(|category| {
+            let phase = format!("compute {} winners", category.category().name);
+            timings.time(&phase, || category.compute_winners(&context))
+        })
+        .collect::<Result<_, WinnerToolError>>()?;
+
+    Ok(ScoringResults {
+        first_slot: summary.first_slot,
+        last_slot: summary.accounts.slot,
+        stakes,
+        winners,
+        parameters: parameters.clone(),
+        timings,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::Report;
+    use solana_ledger::{
+        blockstore::{create_new_ledger, entries_to_test_shreds, make_many_slot_entries},
+        blockstore_processor::process_entries,
+        entry::next_entry_mut,
+    };
+    use solana_runtime::genesis_utils::{create_genesis_config_with_leader, GenesisConfigInfo};
+    use solana_sdk::{
+        signature::{Keypair, KeypairUtil},
+        transaction::Transaction,
+    };
+    use solana_vote_program::{vote_instruction, vote_state::Vote};
+    use std::collections::HashMap;
+
+    // Writes a ledger to `ledger_path` in which the only validator votes on the parent of every
+    // slot up to `last_slot`, returning its genesis config and the identity of the validator
+    fn create_vote_ledger(ledger_path: &Path, last_slot: Slot) -> (GenesisConfig, Pubkey) {
+        let leader_keypair = Keypair::new();
+        let GenesisConfigInfo {
+            genesis_config,
+            mint_keypair,
+            voting_keypair,
+        } = create_genesis_config_with_leader(10_000, &leader_keypair.pubkey(), 1_000);
+        let mut hash = create_new_ledger(ledger_path, &genesis_config).unwrap();
+        let blockstore = Blockstore::open(ledger_path).unwrap();
+
+        // Votes carry the hash of the bank they vote on, so the ledger is replayed as it is written
+        let mut bank = Arc::new(Bank::new(&genesis_config));
+        let entries = blockstore.get_slot_entries(0, 0, None).unwrap();
+        process_entries(&bank, &entries, true).unwrap();
+        bank.freeze();
+
+        for slot in 1..=last_slot {
+            let vote = Vote::new(vec![bank.slot()], bank.hash());
+            let instruction =
+                vote_instruction::vote(&voting_keypair.pubkey(), &voting_keypair.pubkey(), vote);
+            let transaction = Transaction::new_signed_with_payer(
+                vec![instruction],
+                Some(&mint_keypair.pubkey()),
+                &[&mint_keypair, &voting_keypair],
+                hash,
+            );
+            let mut entries = vec![next_entry_mut(&mut hash, 1, vec![transaction])];
+            for _ in 0..genesis_config.ticks_per_slot {
+                entries.push(next_entry_mut(&mut hash, 1, vec![]));
+            }
+
+            bank = Arc::new(Bank::new_from_parent(&bank, &leader_keypair.pubkey(), slot));
+            process_entries(&bank, &entries, true).unwrap();
+            bank.freeze();
+
+            let shreds = entries_to_test_shreds(entries, slot, slot - 1, true);
+            blockstore.insert_shreds(shreds, None, true).unwrap();
+        }
+        (genesis_config, leader_keypair.pubkey())
+    }
+
+    #[test]
+    fn test_replay_threads() {
+        let ledger_dir = tempfile::tempdir().unwrap();
+        let (genesis_config, validator) = create_vote_ledger(ledger_dir.path(), 40);
+        let blockstore = Blockstore::open(ledger_dir.path()).unwrap();
+
+        let run = |replay_threads: usize| {
+            let options = ScoringOptions {
+                baseline_validator: Some(validator),
+                excluded_set: HashSet::new(),
+                starting_balance: 0,
+                start_slot: None,
+                final_slot: None,
+                parameters: ScoringParameters::default(),
+                checkpoint_path: None,
+                checkpoint_interval: 0,
+                resume_from: None,
+                snapshot_archive: None,
+                replay_threads: Some(replay_threads),
+                verify_poh: false,
+                expected_bank_hashes: BTreeMap::new(),
+                metrics: None,
+            };
+            ScoringSession::new(&genesis_config, &blockstore, options)
+                .run()
+                .unwrap()
+        };
+
+        let results = run(1);
+        assert_eq!(results.last_slot, 40);
+        assert!(results
+            .stakes
+            .iter()
+            .any(|(pubkey, _)| *pubkey == validator));
+
+        // Scores must not depend on how many threads replay transactions
+        let report = Report::new(&results, &HashMap::new()).to_json();
+        for replay_threads in &[2, 4] {
+            let results = run(*replay_threads);
+            assert_eq!(Report::new(&results, &HashMap::new()).to_json(), report);
+        }
+    }
+
+    #[test]
+    fn test_open_backoff() {
+        let poll_interval = Duration::from_secs(10);
+        assert_eq!(open_backoff(poll_interval, 1), Duration::from_secs(20));
+        assert_eq!(open_backoff(poll_interval, 3), Duration::from_secs(80));
+        assert_eq!(open_backoff(poll_interval, 7), MAX_OPEN_BACKOFF);
+        assert_eq!(open_backoff(poll_interval, 100), MAX_OPEN_BACKOFF);
+    }
+
+    #[test]
+    fn test_open_with_retries() {
+        fn failure<T>() -> Result<Option<T>, WinnerToolError> {
+            Err(WinnerToolError::Ledger("unavailable".to_string()))
+        }
+        let poll_interval = Duration::from_secs(1);
+
+        // Without retries the first failure is returned
+        let mut sleeps = vec![];
+        let result: Result<(), _> =
+            open_with_retries(failure::<()>, false, poll_interval, |d| sleeps.push(d));
+        assert!(result.is_err());
+        assert!(sleeps.is_empty());
+
+        // With retries the backoff grows until the ledger has failed to open too often
+        let mut opens = 0;
+        let mut sleeps = vec![];
+        let result: Result<(), _> = open_with_retries(
+            || {
+                opens += 1;
+                failure()
+            },
+            true,
+            poll_interval,
+            |d| sleeps.push(d),
+        );
+        assert!(result.is_err());
+        assert_eq!(opens, MAX_FAILED_OPENS);
+        let expected: Vec<_> = (1..MAX_FAILED_OPENS)
+            .map(|failed_opens| open_backoff(poll_interval, failed_opens))
+            .collect();
+        assert_eq!(sleeps, expected);
+
+        // Waiting for a refresh does not count as a failure
+        let mut outcomes = vec![Ok(Some(7)), Ok(None), failure(), Ok(None)];
+        let mut sleeps = vec![];
+        let result = open_with_retries(
+            || outcomes.pop().unwrap(),
+            true,
+            poll_interval,
+            |d| sleeps.push(d),
+        );
+        assert_eq!(result.unwrap(), 7);
+        assert_eq!(
+            sleeps,
+            vec![poll_interval, open_backoff(poll_interval, 1), poll_interval]
+        );
+    }
+
+    #[test]
+    fn test_watch_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let ledger_path = dir.path().join("ledger");
+        fs::create_dir(&ledger_path).unwrap();
+        let lock_path = watch_lock_path(&ledger_path);
+        assert_eq!(lock_path, watch_lock_path(&ledger_path.join(".")));
+        assert_eq!(lock_path.file_name().unwrap(), "ledger.lock");
+
+        let lock = WatchLock::acquire(lock_path.clone()).unwrap().unwrap();
+        assert!(WatchLock::acquire(lock_path.clone()).unwrap().is_none());
+        assert!(WatchedLedger::try_open(&ledger_path).unwrap().is_none());
+        drop(lock);
+        assert!(!lock_path.exists());
+
+        let ledger = WatchedLedger::try_open(&ledger_path).unwrap().unwrap();
+        assert!(lock_path.exists());
+        drop(ledger);
+        assert!(!lock_path.exists());
+    }
+
+    #[test]
+    fn test_next_watch_slot() {
+        let ledger_dir = tempfile::tempdir().unwrap();
+        let blockstore = Blockstore::open(ledger_dir.path()).unwrap();
+        let (shreds, _) = make_many_slot_entries(0, 50, 1);
+        blockstore.insert_shreds(shreds, None, false).unwrap();
+        blockstore.set_roots(&[1, 2, 3, 5, 33, 40]).unwrap();
+        let epoch_schedule = EpochSchedule::custom(32, 32, false);
+
+        assert_eq!(next_watch_slot(&blockstore, &epoch_schedule, 1, 2), Some(3));
+        // The first root of the next epoch comes before the interval has passed
+        assert_eq!(
+            next_watch_slot(&blockstore, &epoch_schedule, 5, 100),
+            Some(33)
+        );
+        // Slots that have not been rooted yet are not scored
+        assert_eq!(next_watch_slot(&blockstore, &epoch_schedule, 40, 2), None);
+    }
+}
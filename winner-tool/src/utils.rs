@@ -1,101 +1,287 @@
-use crate::winner::Winner;
+use crate::error::WinnerToolError;
+use crate::winner::{EpochStandings, Score, Winner};
+use serde::{Deserialize, Serialize};
 use solana_ledger::blockstore::Blockstore;
-use solana_sdk::clock::Slot;
-use solana_sdk::pubkey::Pubkey;
+use solana_sdk::{
+    clock::{Epoch, Slot},
+    pubkey::Pubkey,
+};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 /// Returns an ordered list of slots for the blockchain ending with `last_block` and starting with
-/// `first_block`
-pub fn block_chain(first_block: Slot, last_block: Slot, blockstore: &Blockstore) -> Vec<Slot> {
+/// the last block at or before `first_slot`
+pub fn block_chain(
+    first_slot: Slot,
+    last_block: Slot,
+    blockstore: &Blockstore,
+) -> Result<Vec<Slot>, WinnerToolError> {
     let mut block_chain = Vec::new();
     let mut block_slot = last_block;
     loop {
         block_chain.push(block_slot);
-        if block_slot == first_block {
+        if block_slot <= first_slot {
             break;
         }
-        block_slot = blockstore.meta(block_slot).unwrap().unwrap().parent_slot;
+        block_slot = blockstore
+            .meta(block_slot)
+            .map_err(|err| {
+                WinnerToolError::Ledger(format!(
+                    "Unable to read the metadata of slot {}: {:?}",
+                    block_slot, err
+                ))
+            })?
+            .ok_or(WinnerToolError::MissingSlot(block_slot))?
+            .parent_slot;
     }
-    block_chain.into_iter().rev().collect()
+    Ok(block_chain.into_iter().rev().collect())
 }
 
-/// Transforms a validator score into a formatted score string for display purposes
-pub type WinnerTransform = fn(&[(Pubkey, f64)]) -> Vec<Winner>;
+/// Orders scores from best to worst, with scores that are not a number last
+pub fn best_first(a: f64, b: f64) -> Ordering {
+    match (a.is_nan(), b.is_nan()) {
+        (false, false) => b.partial_cmp(&a).unwrap_or(Ordering::Equal),
+        (false, true) => Ordering::Less,
+        (true, false) => Ordering::Greater,
+        (true, true) => Ordering::Equal,
+    }
+}
+
+/// Fractions of the baseline score that separate the high, medium, low and bottom buckets
+pub type BaselineBuckets = [f64; 3];
 
-const HIGH_BUCKET: &str = "Greater than 95% of the baseline";
-const MEDIUM_BUCKET: &str = "95% to 75% of the baseline";
-const LOW_BUCKET: &str = "75% to 50% of the baseline";
-const BOTTOM_BUCKET: &str = "Under 50% of the baseline";
+pub const DEFAULT_BASELINE_BUCKETS: BaselineBuckets = [0.95, 0.75, 0.5];
+
+pub fn validate_baseline_buckets(buckets: &BaselineBuckets) -> Result<(), WinnerToolError> {
+    if buckets.windows(2).all(|pair| pair[0] >= pair[1]) && buckets[2] >= 0. {
+        Ok(())
+    } else {
+        Err(WinnerToolError::InvalidArgument(format!(
+            "Baseline buckets {:?} must be non-negative and in descending order",
+            buckets
+        )))
+    }
+}
+
+// Formats a fraction as a percentage without trailing zeros, e.g. 0.95 => 95
+fn percent(fraction: f64) -> f64 {
+    (fraction * 10_000.).round() / 100.
+}
 
 /// Bucket winners relative to the Solana validator baseline.
 pub fn bucket_winners(
-    results: &[(Pubkey, f64)],
+    results: &[Winner],
     baseline: f64,
-    winner_transform: WinnerTransform,
+    buckets: &BaselineBuckets,
 ) -> Vec<(String, Vec<Winner>)> {
     let find_bucket_index = |value: f64| -> usize {
         results
             .iter()
-            .rposition(|&result| result.1 > value)
+            .rposition(|result| result.1.value() > value)
             .map(|position| position + 1)
             .unwrap_or(0)
     };
 
     let mut bucket_winners = Vec::new();
 
-    let high_bucket_index = find_bucket_index(0.95 * baseline);
+    let [high_fraction, medium_fraction, low_fraction] = *buckets;
+
+    let high_bucket_index = find_bucket_index(high_fraction * baseline);
     let high = &results[..high_bucket_index];
-    bucket_winners.push((HIGH_BUCKET.to_string(), winner_transform(high)));
+    let high_bucket = format!("Greater than {}% of the baseline", percent(high_fraction));
+    bucket_winners.push((high_bucket, high.to_vec()));
 
-    let medium_bucket_index = find_bucket_index(0.75 * baseline);
+    let medium_bucket_index = find_bucket_index(medium_fraction * baseline);
     let medium = &results[high_bucket_index..medium_bucket_index];
-    bucket_winners.push((MEDIUM_BUCKET.to_string(), winner_transform(medium)));
+    let medium_bucket = format!(
+        "{}% to {}% of the baseline",
+        percent(high_fraction),
+        percent(medium_fraction)
+    );
+    bucket_winners.push((medium_bucket, medium.to_vec()));
 
-    let low_bucket_index = find_bucket_index(0.5 * baseline);
+    let low_bucket_index = find_bucket_index(low_fraction * baseline);
     let low = &results[medium_bucket_index..low_bucket_index];
-    bucket_winners.push((LOW_BUCKET.to_string(), winner_transform(low)));
+    let low_bucket = format!(
+        "{}% to {}% of the baseline",
+        percent(medium_fraction),
+        percent(low_fraction)
+    );
+    bucket_winners.push((low_bucket, low.to_vec()));
 
     let bottom_bucket_index = find_bucket_index(-1.);
     let bottom = &results[low_bucket_index..bottom_bucket_index];
-    bucket_winners.push((BOTTOM_BUCKET.to_string(), winner_transform(bottom)));
+    let bottom_bucket = format!("Under {}% of the baseline", percent(low_fraction));
+    bucket_winners.push((bottom_bucket, bottom.to_vec()));
 
     bucket_winners
 }
 
+/// Cumulative per-validator totals recorded when replay first reaches each epoch, from which the
+/// change over every epoch is derived
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct EpochTotals {
+    totals: Vec<(Epoch, HashMap<Pubkey, i64>)>,
+}
+
+impl EpochTotals {
+    /// Whether the totals at the start of `epoch` still need to be recorded
+    pub fn is_new_epoch(&self, epoch: Epoch) -> bool {
+        self.totals
+            .last()
+            .map(|(last_epoch, _)| *last_epoch < epoch)
+            .unwrap_or(true)
+    }
+
+    pub fn record(&mut self, epoch: Epoch, totals: HashMap<Pubkey, i64>) {
+        self.totals.push((epoch, totals));
+    }
+
+    /// Change of every total over each recorded epoch, the last of which ends with
+    /// `final_totals`. Totals that were not recorded at the start of an epoch count as `initial`.
+    pub fn deltas(
+        &self,
+        final_totals: &HashMap<Pubkey, i64>,
+        initial: i64,
+    ) -> BTreeMap<Epoch, HashMap<Pubkey, i64>> {
+        let mut deltas = BTreeMap::new();
+        for (index, (epoch, start_totals)) in self.totals.iter().enumerate() {
+            let end_totals = self
+                .totals
+                .get(index + 1)
+                .map(|(_, totals)| totals)
+                .unwrap_or(final_totals);
+            let epoch_deltas = end_totals
+                .iter()
+                .map(|(key, total)| {
+                    let start = start_totals.get(key).cloned().unwrap_or(initial);
+                    (*key, total - start)
+                })
+                .collect();
+            deltas.insert(*epoch, epoch_deltas);
+        }
+        deltas
+    }
+}
+
+/// Ranks the scores of each epoch from best to worst, leaving out `excluded_set`
+pub fn epoch_standings(
+    epoch_scores: BTreeMap<Epoch, HashMap<Pubkey, Score>>,
+    excluded_set: &HashSet<Pubkey>,
+) -> Vec<EpochStandings> {
+    epoch_scores
+        .into_iter()
+        .map(|(epoch, scores)| {
+            let mut results: Vec<Winner> = scores
+                .into_iter()
+                .filter(|(key, _)| !excluded_set.contains(key))
+                .collect();
+            results.sort_by(|a, b| best_first(a.1.value(), b.1.value()));
+            EpochStandings { epoch, results }
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    fn normalize_winners(winners: &[(Pubkey, f64)]) -> Vec<Winner> {
-        winners
-            .iter()
-            .map(|(key, score)| (*key, score.to_string()))
-            .collect()
-    }
-
     #[test]
     fn test_bucket_winners() {
         let mut results = Vec::new();
 
-        let expected_hi_bucket = vec![(Pubkey::new_rand(), 1.00), (Pubkey::new_rand(), 0.96)];
+        let expected_hi_bucket = vec![
+            (Pubkey::new_rand(), Score::Ratio(1.00)),
+            (Pubkey::new_rand(), Score::Ratio(0.96)),
+        ];
 
         let expected_md_bucket = vec![];
 
         let expected_lo_bucket = vec![
-            (Pubkey::new_rand(), 0.75),
-            (Pubkey::new_rand(), 0.75),
-            (Pubkey::new_rand(), 0.51),
+            (Pubkey::new_rand(), Score::Ratio(0.75)),
+            (Pubkey::new_rand(), Score::Ratio(0.75)),
+            (Pubkey::new_rand(), Score::Ratio(0.51)),
         ];
 
         results.extend(expected_hi_bucket.iter());
         results.extend(expected_md_bucket.iter());
         results.extend(expected_lo_bucket.iter());
-        results.push((Pubkey::new_rand(), 0.50));
+        results.push((Pubkey::new_rand(), Score::Ratio(0.50)));
 
         let baseline = 1.0;
-        let bucket_winners = bucket_winners(&results, baseline, normalize_winners);
+        let bucket_winners = bucket_winners(&results, baseline, &DEFAULT_BASELINE_BUCKETS);
+
+        assert_eq!(bucket_winners[0].0, "Greater than 95% of the baseline");
+        assert_eq!(bucket_winners[0].1, expected_hi_bucket);
+        assert_eq!(bucket_winners[1].0, "95% to 75% of the baseline");
+        assert_eq!(bucket_winners[1].1, expected_md_bucket);
+        assert_eq!(bucket_winners[2].0, "75% to 50% of the baseline");
+        assert_eq!(bucket_winners[2].1, expected_lo_bucket);
+        assert_eq!(bucket_winners[3].0, "Under 50% of the baseline");
+    }
+
+    #[test]
+    fn test_best_first() {
+        let mut scores = vec![0.5, std::f64::NAN, 1.0, 0.75];
+        scores.sort_by(|a, b| best_first(*a, *b));
+        assert_eq!(scores[..3], [1.0, 0.75, 0.5]);
+        assert!(scores[3].is_nan());
+    }
+
+    #[test]
+    fn test_validate_baseline_buckets() {
+        assert!(validate_baseline_buckets(&DEFAULT_BASELINE_BUCKETS).is_ok());
+        assert!(validate_baseline_buckets(&[0.9, 0.9, 0.]).is_ok());
+        assert!(validate_baseline_buckets(&[0.5, 0.75, 0.95]).is_err());
+        assert!(validate_baseline_buckets(&[0.95, 0.75, -0.5]).is_err());
+    }
+
+    #[test]
+    fn test_epoch_totals() {
+        let validator = Pubkey::new_rand();
+        let late_joiner = Pubkey::new_rand();
+        let totals = |entries: &[(Pubkey, i64)]| -> HashMap<Pubkey, i64> {
+            entries.iter().cloned().collect()
+        };
+
+        let mut epoch_totals = EpochTotals::default();
+        assert!(epoch_totals.is_new_epoch(0));
+        epoch_totals.record(0, totals(&[(validator, 100)]));
+        assert!(!epoch_totals.is_new_epoch(0));
+        assert!(epoch_totals.is_new_epoch(1));
+        epoch_totals.record(1, totals(&[(validator, 150), (late_joiner, 80)]));
+
+        let deltas = epoch_totals.deltas(&totals(&[(validator, 140), (late_joiner, 90)]), 100);
+        assert_eq!(deltas.len(), 2);
+        assert_eq!(deltas[&0], totals(&[(validator, 50), (late_joiner, -20)]));
+        assert_eq!(deltas[&1], totals(&[(validator, -10), (late_joiner, 10)]));
+    }
+
+    #[test]
+    fn test_epoch_standings() {
+        let top_validator = Pubkey::new_rand();
+        let bottom_validator = Pubkey::new_rand();
+        let excluded_validator = Pubkey::new_rand();
+        let mut excluded_set = HashSet::new();
+        excluded_set.insert(excluded_validator);
+
+        let mut epoch_scores = BTreeMap::new();
+        let mut scores = HashMap::new();
+        scores.insert(bottom_validator, Score::Points(-1));
+        scores.insert(top_validator, Score::Points(3));
+        scores.insert(excluded_validator, Score::Points(7));
+        epoch_scores.insert(4, scores);
 
-        assert_eq!(bucket_winners[0].1, normalize_winners(&expected_hi_bucket));
-        assert_eq!(bucket_winners[1].1, normalize_winners(&expected_md_bucket));
-        assert_eq!(bucket_winners[2].1, normalize_winners(&expected_lo_bucket));
+        let standings = epoch_standings(epoch_scores, &excluded_set);
+        assert_eq!(
+            standings,
+            vec![EpochStandings {
+                epoch: 4,
+                results: vec![
+                    (top_validator, Score::Points(3)),
+                    (bottom_validator, Score::Points(-1))
+                ],
+            }]
+        );
     }
 }
@@ -1,16 +1,207 @@
-use solana_sdk::pubkey::Pubkey;
+use serde::Serialize;
+use solana_sdk::{clock::Epoch, native_token::lamports_to_sol, pubkey::Pubkey};
+use std::{collections::HashMap, fmt};
 
-#[derive(Debug)]
-pub enum Category {
-    Availability(String),
-    ConfirmationLatency(String),
-    RewardsEarned,
+/// A scoring category of Tour de SOL
+#[derive(Clone, Copy, Debug)]
+pub struct Category {
+    /// Stable identifier used to select the category and by machine-readable reports
+    pub name: &'static str,
+    /// Human readable name of the category
+    pub title: &'static str,
+    /// Describes a validator's score in the terms of this category
+    pub describe: fn(&Score) -> String,
 }
 
-pub type Winner = (Pubkey, String);
+impl Category {
+    pub fn describe_score(&self, score: &Score) -> String {
+        (self.describe)(score)
+    }
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.title)
+    }
+}
+
+/// Score of a validator in a category. Serializes as the bare number.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum Score {
+    /// Lamports gained or lost
+    Lamports(i64),
+    /// Fraction of the best possible result
+    Ratio(f64),
+    /// Points accumulated over the ledger
+    Points(i64),
+}
+
+impl Score {
+    /// Numeric value of the score used for ranking and bucketing
+    pub fn value(&self) -> f64 {
+        match self {
+            Score::Lamports(lamports) => *lamports as f64,
+            Score::Ratio(ratio) => *ratio,
+            Score::Points(points) => *points as f64,
+        }
+    }
+}
+
+impl fmt::Display for Score {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Score::Lamports(lamports) => {
+                let sign = if *lamports < 0 { "-" } else { "" };
+                let lamports = lamports.abs() as u64;
+                write!(
+                    f,
+                    "{}{:.5} SOL ({}{} lamports)",
+                    sign,
+                    lamports_to_sol(lamports),
+                    sign,
+                    lamports
+                )
+            }
+            Score::Ratio(ratio) => write!(f, "{:.*}%", 3, ratio * 100f64),
+            Score::Points(points) => write!(f, "{}", points),
+        }
+    }
+}
+
+pub type Winner = (Pubkey, Score);
+
+/// Named raw measurements a validator's score was derived from
+pub type ValidatorDetails = Vec<(&'static str, u64)>;
+
+/// Standings of the validators over a single epoch
+#[derive(Clone, Debug, PartialEq)]
+pub struct EpochStandings {
+    pub epoch: Epoch,
+    /// Every validator that took part in the epoch, sorted from best to worst
+    pub results: Vec<Winner>,
+}
+
+/// Rank and score of a validator in each epoch, `None` where it did not take part
+pub type EpochRow = (Pubkey, Vec<Option<(usize, Score)>>);
 
 pub struct Winners {
     pub category: Category,
+    /// Score of the Solana baseline validator, if the category is measured against one
+    pub baseline: Option<Score>,
+    /// Every ranked validator, sorted from best to worst
+    pub results: Vec<Winner>,
     pub top_winners: Vec<Winner>,
     pub bucket_winners: Vec<(String, Vec<Winner>)>,
+    pub details: HashMap<Pubkey, ValidatorDetails>,
+    /// Standings within each epoch of the ledger
+    pub epochs: Vec<EpochStandings>,
+}
+
+impl Winners {
+    /// Describes the baseline the category was scored against, if any
+    pub fn describe_baseline(&self) -> Option<String> {
+        self.baseline
+            .map(|baseline| format!("Baseline: {}", self.category.describe_score(&baseline)))
+    }
+
+    /// Per-epoch rank and score of every validator, in the order of the overall results
+    pub fn epoch_table(&self) -> Vec<EpochRow> {
+        let mut identities: Vec<Pubkey> = self.results.iter().map(|(pubkey, _)| *pubkey).collect();
+        for standings in &self.epochs {
+            for (pubkey, _) in &standings.results {
+                if !identities.contains(pubkey) {
+                    identities.push(*pubkey);
+                }
+            }
+        }
+
+        let epoch_ranks: Vec<HashMap<Pubkey, (usize, Score)>> = self
+            .epochs
+            .iter()
+            .map(|standings| {
+                standings
+                    .results
+                    .iter()
+                    .enumerate()
+                    .map(|(index, (pubkey, score))| (*pubkey, (index + 1, *score)))
+                    .collect()
+            })
+            .collect();
+
+        identities
+            .into_iter()
+            .map(|pubkey| {
+                let ranks = epoch_ranks
+                    .iter()
+                    .map(|ranks| ranks.get(&pubkey).cloned())
+                    .collect();
+                (pubkey, ranks)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_score_display() {
+        assert_eq!(
+            Score::Lamports(-1_500_000_000).to_string(),
+            "-1.50000 SOL (-1500000000 lamports)"
+        );
+        assert_eq!(Score::Ratio(0.98765).to_string(), "98.765%");
+        assert_eq!(Score::Points(-3).to_string(), "-3");
+    }
+
+    #[test]
+    fn test_epoch_table() {
+        let describe = |score: &Score| score.to_string();
+        let first = Pubkey::new_rand();
+        let second = Pubkey::new_rand();
+        let late_joiner = Pubkey::new_rand();
+        let winners = Winners {
+            category: Category {
+                name: "test",
+                title: "Test",
+                describe,
+            },
+            baseline: None,
+            results: vec![(first, Score::Points(5)), (second, Score::Points(3))],
+            top_winners: vec![],
+            bucket_winners: vec![],
+            details: HashMap::new(),
+            epochs: vec![
+                EpochStandings {
+                    epoch: 0,
+                    results: vec![(second, Score::Points(2)), (first, Score::Points(1))],
+                },
+                EpochStandings {
+                    epoch: 1,
+                    results: vec![
+                        (first, Score::Points(4)),
+                        (late_joiner, Score::Points(2)),
+                        (second, Score::Points(1)),
+                    ],
+                },
+            ],
+        };
+
+        assert_eq!(
+            winners.epoch_table(),
+            vec![
+                (
+                    first,
+                    vec![Some((2, Score::Points(1))), Some((1, Score::Points(4)))]
+                ),
+                (
+                    second,
+                    vec![Some((1, Score::Points(2))), Some((3, Score::Points(1)))]
+                ),
+                (late_joiner, vec![None, Some((2, Score::Points(2)))]),
+            ]
+        );
+    }
 }